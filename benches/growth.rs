@@ -0,0 +1,53 @@
+// Compares StringBuffer growth policies on a 10MB append workload.
+//
+// Run with `cargo bench --bench growth`.
+
+extern crate strings;
+
+use std::time::{Duration, Instant};
+use strings::string_buffer::{GrowthPolicy, StringBuffer};
+
+const TOTAL_LEN: usize = 10 * 1024 * 1024;
+const ITERATIONS: u32 = 5;
+
+fn main() {
+    // No newlines, so `cur_offset` must search every node.
+    let text = "let x = some_function(argument_one, argument_two); ";
+
+    let policies = [("geometric x2, max 64kB (default)", GrowthPolicy::default()),
+                    ("geometric x2, max 1MB", GrowthPolicy::Geometric { factor: 2, max: 0x100000 }),
+                    ("geometric x4, max 4MB", GrowthPolicy::Geometric { factor: 4, max: 0x400000 }),
+                    ("fixed 4kB", GrowthPolicy::Fixed(0x1000)),
+                    ("fixed 1MB", GrowthPolicy::Fixed(0x100000))];
+
+    for &(name, policy) in &policies {
+        let mut append = Duration::new(0, 0);
+        let mut chars = Duration::new(0, 0);
+        let mut cur_offset = Duration::new(0, 0);
+
+        for _ in 0..ITERATIONS {
+            let start = Instant::now();
+            let mut buf = StringBuffer::with_growth_policy(0xff, policy);
+            while buf.len() < TOTAL_LEN {
+                buf.push_str(text);
+            }
+            append += start.elapsed();
+
+            let start = Instant::now();
+            let count = buf.chars().count();
+            chars += start.elapsed();
+            assert!(count == buf.len());
+
+            let start = Instant::now();
+            let offset = buf.cur_offset();
+            cur_offset += start.elapsed();
+            assert!(offset == buf.len());
+        }
+
+        println!("{:<34} append: {:>10?}  chars: {:>10?}  cur_offset: {:>10?}",
+                 name,
+                 append / ITERATIONS,
+                 chars / ITERATIONS,
+                 cur_offset / ITERATIONS);
+    }
+}
@@ -0,0 +1,22 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// An edit in a batch passed to `Rope::apply_edits` or
+// `StringBuffer::apply_edits`. Offsets are in bytes, and refer to the text
+// before any edit in the batch is applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edit {
+    // Inserts text at byte offset at.
+    Insert { at: usize, text: String },
+    // Removes the text from start to end.
+    Remove { start: usize, end: usize },
+    // Overwrites text.len() bytes at at with text, see `Rope::replace_str`.
+    Replace { at: usize, text: String },
+}
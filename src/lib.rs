@@ -3,19 +3,36 @@
 // TODO use crates.io log instead
 #[macro_use]
 extern crate log;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(feature = "unicode")]
+extern crate unicode_segmentation;
 
 // TODO should probably expose data structures, not the modules
 pub mod string_buffer;
+pub mod edit;
 
 mod util;
 mod ropes;
 
 pub mod rope {
     pub use ::ropes::RopeSlice;
+    pub use ::ropes::RopeBytes;
+    pub use ::ropes::RopeChars;
+    pub use ::ropes::RopeLines;
+    pub use ::ropes::RopeLeaves;
+    pub use ::ropes::RopeSnapshot;
     pub use ::ropes::Rope;
 }
 
 pub mod src_rope {
     pub use ::ropes::SrcRopeSlice as RopeSlice;
+    pub use ::ropes::SrcRopeBytes as RopeBytes;
+    pub use ::ropes::SrcRopeChars as RopeChars;
+    pub use ::ropes::SrcRopeLines as RopeLines;
+    pub use ::ropes::SrcRopeLeaves as RopeLeaves;
+    pub use ::ropes::SrcCharPositions as CharPositions;
     pub use ::ropes::SrcRope as Rope;
 }
@@ -7,11 +7,22 @@ macro_rules! impl_rope {
                 self.len
             }
 
+            pub fn is_empty(&self) -> bool {
+                self.len == 0
+            }
+
             pub fn insert_copy(&mut self, start: usize, text: &str) {
                 // FIXME If we did clever things with allocation, we could do better here.
                 self.insert(start, text.to_string());
             }
 
+            // Inserts ch without going through a String, the char's bytes are
+            // copied straight into the storage for its leaf.
+            pub fn insert_char(&mut self, start: usize, ch: char) {
+                let mut buf = [0; 4];
+                self.insert_bytes(start, ch.encode_utf8(&mut buf).as_bytes().to_vec());
+            }
+
             pub fn push(&mut self, text: String) {
                 let len = self.len();
                 self.insert(len, text);
@@ -23,6 +34,19 @@ macro_rules! impl_rope {
                 self.insert(len, text.to_string());
             }
 
+            // Shortens the rope to new_len bytes, which must be on a char
+            // boundary. Does nothing if new_len is not less than the length.
+            pub fn truncate(&mut self, new_len: usize) {
+                if new_len >= self.len {
+                    return;
+                }
+                assert!(self.is_char_boundary(new_len), "truncate is not on a char boundary");
+                let len = self.len;
+                self.remove(new_len, len);
+            }
+
+            // `do_remove` must report the change in length of the root exactly,
+            // in particular it must never remove more than the whole rope.
             fn remove_inner<F>(&mut self,
                                start: usize,
                                end: usize,
@@ -40,8 +64,12 @@ macro_rules! impl_rope {
                         self.root = Node::empty_inner();
                         self.len = 0;
                     }
-                    NodeAction::Adjust(adj) => self.len = (self.len as isize + adj) as usize,
+                    NodeAction::Adjust(adj) => {
+                        debug_assert!(self.len as isize + adj >= 0, "removed more than the whole rope");
+                        self.len = (self.len as isize + adj) as usize;
+                    }
                     NodeAction::Change(node, adj) => {
+                        debug_assert!(self.len as isize + adj >= 0, "removed more than the whole rope");
                         self.root = *node;
                         self.len = (self.len as isize + adj) as usize;
                     }
@@ -53,35 +81,262 @@ macro_rules! impl_rope {
             // has the same size as new_char.
             pub fn replace(&mut self, start: usize, new_char: char) {
                 assert!(start + new_char.len_utf8() <= self.len);
-                // This is pretty wasteful in that we're allocating for no point, but
-                // I think that is better than duplicating a bunch of code.
-                // It should be possible to view a &char as a &[u8] somehow, and then
-                // we can optimise this (FIXME).
-                self.replace_str(start, &new_char.to_string()[..]);
+                self.replace_str(start, new_char.encode_utf8(&mut [0; 4]));
             }
 
+            // Overwrites the text at start with new_str. The replaced region must
+            // be the same length as new_str and consist of whole chars, i.e., it
+            // must start and end on char boundaries.
             pub fn replace_str(&mut self, start: usize, new_str: &str) {
-                assert!(start + new_str.len() <= self.len);
-                self.root.replace(start, new_str);
+                let end = start + new_str.len();
+                assert!(end <= self.len);
+                assert!(self.is_char_boundary(start) && self.is_char_boundary(end),
+                        "replace_str {}..{} is not on char boundaries",
+                        start,
+                        end);
+                self.unshare_storage();
+                self.root.replace(start, new_str.as_bytes());
             }
 
-            pub fn slice(&self, Range { start, end }: Range<usize>) -> RopeSlice {
-                // This could be true for two cases
-                //    1. The Rope is empty (start == end == self.len == 0)
-                //    2. Attempting to slice the end of the rope (start == end == self.len)
-                if start == end {
-                    return RopeSlice::empty();
-                }
+            // Any kind of range can be used, e.g., `rope.slice(2..)`. Note that
+            // ropes can't implement `Index`, since it must return a reference and
+            // a `RopeSlice` is created on demand.
+            pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> RopeSlice<'_> {
+                let (start, end) = self.range_offsets(&range);
 
-                debug_assert!(end > start && start <= self.len && end <= self.len);
+                // Any range with start <= end <= len is allowed, including empty
+                // ranges anywhere in the rope (e.g., `0..0` or `len..len`).
+                assert!(start <= end && end <= self.len,
+                        "slice {}..{} out of bounds for rope of length {}",
+                        start,
+                        end,
+                        self.len);
 
                 let mut result = RopeSlice::empty();
+                result.offset = start;
+                if start == end {
+                    return result;
+                }
+
                 self.root.find_slice(start, end, &mut result);
                 result
             }
 
+            // Like `slice`, but returns None rather than panicking if the range is
+            // out of bounds or reversed, or does not start and end on char
+            // boundaries.
+            pub fn get<R: RangeBounds<usize>>(&self, range: R) -> Option<RopeSlice<'_>> {
+                let (start, end) = self.range_offsets(&range);
+                if start > end || end > self.len ||
+                   !self.is_char_boundary(start) || !self.is_char_boundary(end) {
+                    return None;
+                }
+                Some(self.slice(start..end))
+            }
+
+            // The start and end offsets of range (which are not checked).
+            fn range_offsets<R: RangeBounds<usize>>(&self, range: &R) -> (usize, usize) {
+                // Saturating, so that e.g. `..=usize::MAX` is out of bounds
+                // rather than overflowing.
+                let start = match range.start_bound() {
+                    Bound::Included(&start) => start,
+                    Bound::Excluded(&start) => start.saturating_add(1),
+                    Bound::Unbounded => 0,
+                };
+                let end = match range.end_bound() {
+                    Bound::Included(&end) => end.saturating_add(1),
+                    Bound::Excluded(&end) => end,
+                    Bound::Unbounded => self.len,
+                };
+                (start, end)
+            }
+
+            // Whether offset is the start of a char, or the end of the rope.
+            pub fn is_char_boundary(&self, offset: usize) -> bool {
+                if offset == self.len {
+                    return true;
+                }
+                self.byte_at(offset).is_some_and(|b| b & 0xc0 != 0x80)
+            }
+
+            pub fn byte_at(&self, offset: usize) -> Option<u8> {
+                if offset >= self.len {
+                    return None;
+                }
+                self.root.byte_at(offset)
+            }
+
+            // Returns None if offset is out of bounds or not on a char boundary.
+            pub fn char_at(&self, offset: usize) -> Option<char> {
+                let first_byte = self.byte_at(offset)?;
+                let width = utf8_char_width(first_byte);
+                if width == 0 {
+                    return None;
+                }
+
+                let mut buf = [first_byte, 0, 0, 0];
+                for (i, b) in buf.iter_mut().enumerate().take(width).skip(1) {
+                    *b = self.byte_at(offset + i)?;
+                }
+                ::std::str::from_utf8(&buf[..width]).ok().and_then(|s| s.chars().next())
+            }
+
             pub fn full_slice(&self) -> RopeSlice {
-                self.slice(0..self.len)
+                self.slice(..)
+            }
+
+            // The number of chars in the rope. Note that this is not cheap, it
+            // requires a scan of the whole rope.
+            pub fn char_len(&self) -> usize {
+                self.full_slice().char_len()
+            }
+
+            // The number of bytes in range, which must be within the rope.
+            pub fn len_in(&self, Range { start, end }: Range<usize>) -> usize {
+                assert!(start <= end && end <= self.len,
+                        "range {}..{} out of bounds for rope of length {}",
+                        start,
+                        end,
+                        self.len);
+                end - start
+            }
+
+            // The number of chars which start in range. Only the leaves which
+            // overlap range are visited, so this is cheaper than `char_len`
+            // for a small part of a large rope.
+            pub fn char_len_in(&self, range: Range<usize>) -> usize {
+                self.slice(range).char_len()
+            }
+
+            // Returns the byte offset of the first occurrence of `pat` in the
+            // rope, if any.
+            pub fn find(&self, pat: &str) -> Option<usize> {
+                self.full_slice().find(pat)
+            }
+
+            // Checks the internal invariants of the rope, returning a description
+            // of the first which does not hold. This walks the whole tree, so is
+            // intended for tests and fuzzing.
+            pub fn verify(&self) -> Result<(), String> {
+                let buffers: Vec<(usize, usize)> =
+                    self.storage.iter().map(|buf| (buf.as_ptr() as usize, buf.len())).collect();
+                let len = self.root.verify(&buffers, true)?;
+                if len != self.len {
+                    return Err(format!("rope length is {}, but its leaves hold {} bytes", self.len, len));
+                }
+                // Leaves may split chars, but the text as a whole must be valid.
+                if let Err(e) = ::std::str::from_utf8(&self.bytes().collect::<Vec<u8>>()) {
+                    return Err(format!("rope text is not valid UTF-8: {}", e));
+                }
+                Ok(())
+            }
+
+            // The number of bytes at the start of self and other which are the
+            // same, rounded down to a char boundary.
+            pub fn common_prefix_len(&self, other: &Rope) -> usize {
+                let mut n = self.bytes().zip(other.bytes()).take_while(|&(a, b)| a == b).count();
+                while !self.is_char_boundary(n) {
+                    n -= 1;
+                }
+                n
+            }
+
+            // The number of bytes at the end of self and other which are the
+            // same, rounded down so that the suffix starts on a char boundary.
+            // The common prefix and suffix may overlap, e.g., for "aa" and "aaa".
+            pub fn common_suffix_len(&self, other: &Rope) -> usize {
+                let (a, b) = (self.full_slice(), other.full_slice());
+                let mut n = a.rev_bytes().zip(b.rev_bytes()).take_while(|&(a, b)| a == b).count();
+                while !self.is_char_boundary(self.len - n) {
+                    n -= 1;
+                }
+                n
+            }
+
+            // An iterator over the lines in the rope, as slices. Lines are
+            // terminated by '\n', which is not included in the slice. As with
+            // `str::lines`, a trailing newline does not start an extra line.
+            pub fn lines(&self) -> RopeLines<'_> {
+                RopeLines {
+                    rope: self,
+                    bytes: self.full_slice().bytes(),
+                    start: 0,
+                }
+            }
+
+            // The number of heap bytes used by the rope, including storage for
+            // text which has been removed from the rope but not freed.
+            pub fn mem_usage(&self) -> usize {
+                // The root node is stored inline, other nodes are boxed.
+                let nodes = (self.root.node_count() - 1) * mem::size_of::<Node>();
+                nodes + self.storage_usage()
+            }
+
+            pub fn leaf_count(&self) -> usize {
+                let mut count = 0;
+                self.root.for_each_leaf(&mut |_| count += 1);
+                count
+            }
+
+            // The number of nodes on the longest path from the root to a leaf.
+            pub fn depth(&self) -> usize {
+                self.root.depth()
+            }
+
+            // An iterator over the text of each leaf in the rope, in order. Leaves
+            // are borrowed without copying, unless a char is split between two
+            // leaves, in which case the whole char is yielded with the first of
+            // them.
+            pub fn leaves(&self) -> RopeLeaves<'_> {
+                RopeLeaves {
+                    data: self.full_slice(),
+                    cur_node: 0,
+                    skip: 0,
+                }
+            }
+
+            // Like `lines`, but lines may also be terminated by "\r\n", which is
+            // not included in the slice. As with `str::lines`, a '\r' which is
+            // not followed by '\n' is part of the line.
+            pub fn lines_crlf(&self) -> impl Iterator<Item = RopeSlice<'_>> + '_ {
+                self.lines().map(move |line| {
+                    let end = line.offset + line.len();
+                    if !line.is_empty() && self.byte_at(end) == Some(b'\n') &&
+                       self.byte_at(end - 1) == Some(b'\r') {
+                        self.slice(line.offset..end - 1)
+                    } else {
+                        line
+                    }
+                })
+            }
+
+            // The lines (see `lines`) which overlap range, starting with the line
+            // containing range.start. Only the lines in range are visited.
+            pub fn lines_in(&self, range: Range<usize>) -> impl Iterator<Item = RopeSlice<'_>> + '_ {
+                assert!(range.start <= range.end && range.end <= self.len,
+                        "lines_in {}..{} out of bounds for rope of length {}",
+                        range.start,
+                        range.end,
+                        self.len);
+
+                let mut line_start = range.start;
+                while line_start > 0 && self.byte_at(line_start - 1) != Some(b'\n') {
+                    line_start -= 1;
+                }
+
+                let lines = RopeLines {
+                    rope: self,
+                    bytes: self.slice(line_start..).bytes(),
+                    start: line_start,
+                };
+                let end = range.end;
+                lines.enumerate()
+                     .take_while(move |&(i, ref line)| i == 0 || line.offset < end)
+                     .map(|(_, line)| line)
+            }
+
+            pub fn bytes(&self) -> RopeBytes<'_> {
+                self.full_slice().bytes()
             }
 
             pub fn chars(&self) -> RopeChars {
@@ -93,5 +348,439 @@ macro_rules! impl_rope {
                 }
             }
         }
+
+        impl<'rope> RopeSlice<'rope> {
+            // The length of the slice in bytes.
+            pub fn len(&self) -> usize {
+                (0..self.nodes.len()).map(|i| self.node_bytes(i).len()).sum()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.len() == 0
+            }
+
+            // The number of chars in the slice.
+            pub fn char_len(&self) -> usize {
+                // Count the bytes which start a char, so that a char split
+                // between two nodes is only counted once.
+                (0..self.nodes.len()).map(|i| {
+                    self.node_bytes(i).iter().filter(|&&b| b & 0xc0 != 0x80).count()
+                }).sum()
+            }
+
+            // A non-consuming version of `iter_chars`.
+            pub fn chars(&self) -> RopeChars<'rope> {
+                self.clone().iter_chars()
+            }
+
+            pub fn bytes(&self) -> RopeBytes<'rope> {
+                RopeBytes {
+                    data: self.clone(),
+                    cur_node: 0,
+                    cur_byte: 0,
+                    remaining: self.len(),
+                }
+            }
+
+            // Writes the text of the slice to w, without building a String.
+            pub fn write_to<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+                for i in 0..self.nodes.len() {
+                    w.write_all(self.node_bytes(i))?;
+                }
+                Ok(())
+            }
+
+            // Returns the slice as a `str` without copying, if the slice is
+            // contiguous (i.e., it falls within a single node). Returns None if
+            // the slice spans multiple nodes, or does not start and end on char
+            // boundaries.
+            pub fn as_str(&self) -> Option<&'rope str> {
+                match self.nodes.len() {
+                    0 => Some(""),
+                    1 => ::std::str::from_utf8(self.node_bytes(0)).ok(),
+                    _ => None,
+                }
+            }
+
+            // Returns the offset (relative to the start of the slice) of the first
+            // occurrence of `pat` in the slice, if any. Matches may span nodes.
+            pub fn find(&self, pat: &str) -> Option<usize> {
+                let pat = pat.as_bytes();
+                if pat.is_empty() {
+                    return Some(0);
+                }
+
+                let mut offset = 0;
+                for i in 0..self.nodes.len() {
+                    let bytes = self.node_bytes(i);
+                    for (j, &b) in bytes.iter().enumerate() {
+                        if b == pat[0] && self.starts_with_at(i, j, pat) {
+                            return Some(offset + j);
+                        }
+                    }
+                    offset += bytes.len();
+                }
+                None
+            }
+
+            // Whether the slice contains `pat` starting at byte j of the i'th node.
+            fn starts_with_at(&self, mut i: usize, mut j: usize, mut pat: &[u8]) -> bool {
+                while !pat.is_empty() {
+                    if i == self.nodes.len() {
+                        return false;
+                    }
+                    let bytes = &self.node_bytes(i)[j..];
+                    let n = ::std::cmp::min(bytes.len(), pat.len());
+                    if bytes[..n] != pat[..n] {
+                        return false;
+                    }
+                    pat = &pat[n..];
+                    i += 1;
+                    j = 0;
+                }
+                true
+            }
+
+            // The bytes in the slice from last to first.
+            fn rev_bytes(&self) -> impl Iterator<Item = u8> + 'rope {
+                let slice = self.clone();
+                (0..self.nodes.len()).rev().flat_map(move |i| slice.node_bytes(i).iter().rev().cloned())
+            }
+
+            // The bytes of the i'th node in the slice which fall within the slice.
+            fn node_bytes(&self, i: usize) -> &'rope [u8] {
+                let node = self.nodes[i];
+                let start = if i == 0 { self.start } else { 0 };
+                let end = if i == self.nodes.len() - 1 { start + self.len } else { node.len };
+                unsafe {
+                    ::std::slice::from_raw_parts((node.text as usize + start) as *const u8,
+                                                 end - start)
+                }
+            }
+        }
+
+        impl<'a> fmt::Display for RopeSlice<'a> {
+            fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+                let n = self.nodes.len();
+                if (0..n).all(|i| ::std::str::from_utf8(self.node_bytes(i)).is_ok()) {
+                    for i in 0..n {
+                        fmt.write_str(unsafe { ::std::str::from_utf8_unchecked(self.node_bytes(i)) })?;
+                    }
+                } else {
+                    // A char straddles two nodes, so decode across node boundaries.
+                    for (c, _) in self.chars() {
+                        fmt.write_str(c.encode_utf8(&mut [0; 4]))?;
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        impl<'a> fmt::Debug for RopeSlice<'a> {
+            fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+                for i in 0..self.nodes.len() {
+                    if i > 0 {
+                        fmt.write_str("|")?;
+                    }
+                    write!(fmt, "\"{}\"", String::from_utf8_lossy(self.node_bytes(i)))?;
+                }
+                Ok(())
+            }
+        }
+
+        impl<'rope> Iterator for RopeBytes<'rope> {
+            type Item = u8;
+            fn next(&mut self) -> Option<u8> {
+                while self.cur_node < self.data.nodes.len() {
+                    let bytes = self.data.node_bytes(self.cur_node);
+                    if self.cur_byte < bytes.len() {
+                        self.cur_byte += 1;
+                        self.remaining -= 1;
+                        return Some(bytes[self.cur_byte - 1]);
+                    }
+
+                    self.cur_node += 1;
+                    self.cur_byte = 0;
+                }
+
+                None
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.remaining, Some(self.remaining))
+            }
+        }
+
+        impl<'rope> ExactSizeIterator for RopeBytes<'rope> {}
+
+        impl<'rope> Iterator for RopeLeaves<'rope> {
+            type Item = Cow<'rope, str>;
+            fn next(&mut self) -> Option<Cow<'rope, str>> {
+                if self.cur_node == self.data.nodes.len() {
+                    return None;
+                }
+
+                let bytes = self.data.node_bytes(self.cur_node);
+                let skip = ::std::cmp::min(self.skip, bytes.len());
+                let bytes = &bytes[skip..];
+                self.skip -= skip;
+                self.cur_node += 1;
+
+                // Find the start of the last char, and check it is complete.
+                let last = bytes.iter().rposition(|&b| b & 0xc0 != 0x80);
+                let mut missing = match last {
+                    Some(i) => (i + utf8_char_width(bytes[i])).saturating_sub(bytes.len()),
+                    None => 0,
+                };
+                if missing == 0 {
+                    return Some(String::from_utf8_lossy(bytes));
+                }
+
+                // Complete the char using the following nodes.
+                let mut text = bytes.to_vec();
+                let mut i = self.cur_node;
+                while missing > 0 && i < self.data.nodes.len() {
+                    let next = self.data.node_bytes(i);
+                    let n = ::std::cmp::min(missing, next.len());
+                    text.extend_from_slice(&next[..n]);
+                    self.skip += n;
+                    missing -= n;
+                    i += 1;
+                }
+                Some(Cow::Owned(String::from_utf8_lossy(&text).into_owned()))
+            }
+        }
+
+        impl<'rope> Iterator for RopeLines<'rope> {
+            type Item = RopeSlice<'rope>;
+            fn next(&mut self) -> Option<RopeSlice<'rope>> {
+                if self.start >= self.rope.len {
+                    return None;
+                }
+
+                let start = self.start;
+                let mut end = start;
+                loop {
+                    match self.bytes.next() {
+                        Some(b'\n') => {
+                            self.start = end + 1;
+                            break;
+                        }
+                        Some(_) => end += 1,
+                        None => {
+                            self.start = end;
+                            break;
+                        }
+                    }
+                }
+                Some(self.rope.slice(start..end))
+            }
+        }
+
+        impl Default for $ty {
+            fn default() -> Rope {
+                Rope::new()
+            }
+        }
+
+        impl PartialEq for $ty {
+            fn eq(&self, other: &Rope) -> bool {
+                // Shortcut if sizes differ
+                if self.len != other.len {
+                    return false;
+                }
+
+                self.chars().eq(other.chars())
+            }
+        }
+
+        impl Eq for $ty {}
+
+        // Consistent with `PartialEq`, so the shape of the tree does not affect
+        // the hash. Hashers are not required to give the same result for data
+        // written in different sized pieces, so the text is written in pieces
+        // of a fixed size rather than by leaf.
+        impl ::std::hash::Hash for $ty {
+            fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+                let mut buf = [0; 256];
+                let mut len = 0;
+                for b in self.bytes() {
+                    buf[len] = b;
+                    len += 1;
+                    if len == buf.len() {
+                        state.write(&buf);
+                        len = 0;
+                    }
+                }
+                state.write(&buf[..len]);
+                state.write_u8(0xff);
+            }
+        }
+
+        impl PartialEq<str> for $ty {
+            fn eq(&self, other: &str) -> bool {
+                // Shortcut if sizes differ
+                if self.len != other.len() {
+                    return false;
+                }
+
+                self.chars().map(|(c, _)| c).eq(other.chars())
+            }
+        }
+
+        impl<'a> PartialEq<&'a str> for $ty {
+            fn eq(&self, other: &&'a str) -> bool {
+                *self == **other
+            }
+        }
+
+        impl Node {
+            // Returns None if offset is past the end of the node.
+            fn byte_at(&self, offset: usize) -> Option<u8> {
+                self.text_at(offset).map(|text| unsafe { *text })
+            }
+
+            // The address of the byte at offset, or None if offset is past the end
+            // of the node.
+            fn text_at(&self, offset: usize) -> Option<*const u8> {
+                match *self {
+                    Node::InnerNode(Inode { ref left, ref right, weight, .. }) => {
+                        if offset < weight {
+                            left.as_ref().and_then(|left| left.text_at(offset))
+                        } else {
+                            right.as_ref().and_then(|right| right.text_at(offset - weight))
+                        }
+                    }
+                    Node::LeafNode(Lnode { text, len, .. }) => {
+                        if offset >= len {
+                            return None;
+                        }
+                        Some((text as usize + offset) as *const u8)
+                    }
+                }
+            }
+
+            // Calls f on each leaf in the tree rooted at self, in order.
+            fn for_each_leaf<'a, F>(&'a self, f: &mut F)
+                where F: FnMut(&'a Lnode)
+            {
+                match *self {
+                    Node::InnerNode(Inode { ref left, ref right, .. }) => {
+                        if let Some(ref left) = *left {
+                            left.for_each_leaf(f);
+                        }
+                        if let Some(ref right) = *right {
+                            right.for_each_leaf(f);
+                        }
+                    }
+                    Node::LeafNode(ref l) => f(l),
+                }
+            }
+
+            // Writes the tree rooted at self, one node per line, indented by
+            // depth. Missing children are written as `()`.
+            fn fmt_tree(&self, fmt: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+                let indent = depth * 2;
+                match *self {
+                    Node::InnerNode(Inode { ref left, ref right, weight, .. }) => {
+                        writeln!(fmt, "{:2$}inner; {}", "", weight, indent)?;
+                        for child in &[left, right] {
+                            match **child {
+                                Some(ref child) => child.fmt_tree(fmt, depth + 1)?,
+                                None => writeln!(fmt, "{:1$}()", "", indent + 2)?,
+                            }
+                        }
+                        Ok(())
+                    }
+                    Node::LeafNode(Lnode { text, len, .. }) => {
+                        let text = unsafe { ::std::slice::from_raw_parts(text, len) };
+                        writeln!(fmt, "{:3$}{:?}; {}", "", String::from_utf8_lossy(text), len, indent)
+                    }
+                }
+            }
+
+            // Checks the invariants of the tree rooted at self, see `Rope::verify`.
+            // buffers are the start and length of each storage buffer. Returns
+            // the length of the text in the tree.
+            fn verify(&self, buffers: &[(usize, usize)], is_root: bool) -> Result<usize, String> {
+                match *self {
+                    Node::InnerNode(Inode { ref left, ref right, weight, .. }) => {
+                        if !is_root && left.is_none() && right.is_none() {
+                            return Err("inner node has no children".to_owned());
+                        }
+                        let left_len = match *left {
+                            Some(ref left) => left.verify(buffers, false)?,
+                            None => 0,
+                        };
+                        if weight != left_len {
+                            return Err(format!("inner node has weight {}, but its left child has length {}",
+                                               weight,
+                                               left_len));
+                        }
+                        let right_len = match *right {
+                            Some(ref right) => right.verify(buffers, false)?,
+                            None => 0,
+                        };
+                        Ok(left_len + right_len)
+                    }
+                    Node::LeafNode(Lnode { text, len, .. }) => {
+                        if len == 0 {
+                            return Err("leaf is empty".to_owned());
+                        }
+                        let start = text as usize;
+                        if !buffers.iter().any(|&(buf, buf_len)| start >= buf && start + len <= buf + buf_len) {
+                            return Err(format!("leaf text {:#x}, length {} is outside of rope storage", start, len));
+                        }
+                        Ok(len)
+                    }
+                }
+            }
+
+            // The number of nodes (inner and leaf) in the tree rooted at self.
+            fn node_count(&self) -> usize {
+                match *self {
+                    Node::InnerNode(Inode { ref left, ref right, .. }) => {
+                        1 + left.as_ref().map_or(0, |n| n.node_count()) +
+                            right.as_ref().map_or(0, |n| n.node_count())
+                    }
+                    Node::LeafNode(_) => 1,
+                }
+            }
+
+            // The number of nodes on the longest path from self to a leaf.
+            fn depth(&self) -> usize {
+                match *self {
+                    Node::InnerNode(Inode { ref left, ref right, .. }) => {
+                        let left = left.as_ref().map_or(0, |n| n.depth());
+                        let right = right.as_ref().map_or(0, |n| n.depth());
+                        1 + ::std::cmp::max(left, right)
+                    }
+                    Node::LeafNode(_) => 1,
+                }
+            }
+
+            // Calls f on each leaf in the tree rooted at self, in order.
+            fn for_each_leaf_mut<F>(&mut self, f: &mut F)
+                where F: FnMut(&mut Lnode)
+            {
+                match *self {
+                    Node::InnerNode(Inode { ref mut left, ref mut right, .. }) => {
+                        if let Some(ref mut left) = *left {
+                            left.for_each_leaf_mut(f);
+                        }
+                        if let Some(ref mut right) = *right {
+                            right.for_each_leaf_mut(f);
+                        }
+                    }
+                    Node::LeafNode(ref mut l) => f(l),
+                }
+            }
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+                write!(fmt, "{}", self.root)
+            }
+        }
     }
 }
@@ -6,6 +6,16 @@ mod src_rope;
 
 pub use self::rope::Rope;
 pub use self::rope::RopeSlice;
+pub use self::rope::RopeBytes;
+pub use self::rope::RopeChars;
+pub use self::rope::RopeLines;
+pub use self::rope::RopeLeaves;
+pub use self::rope::RopeSnapshot;
 
 pub use self::src_rope::Rope as SrcRope;
 pub use self::src_rope::RopeSlice as SrcRopeSlice;
+pub use self::src_rope::RopeBytes as SrcRopeBytes;
+pub use self::src_rope::RopeChars as SrcRopeChars;
+pub use self::src_rope::RopeLines as SrcRopeLines;
+pub use self::src_rope::RopeLeaves as SrcRopeLeaves;
+pub use self::src_rope::CharPositions as SrcCharPositions;
@@ -12,14 +12,20 @@
 // ----
 // docs - mod docs, item docs
 // tests
-// impl Default, Extend
+// impl Extend
 // impl DoubleEndedIter and ExactSizeIter for RopeChars
 // better allocation
-// balancing?
 
-use std::fmt;
-use std::ops::Range;
-use util::utf8_char_width;
+use std::borrow::Cow;
+use std::{cmp, fmt, io, mem};
+use std::ops::{Bound, Range, RangeBounds};
+use std::rc::Rc;
+use edit::Edit;
+use util::{edit_order, utf8_char_width};
+
+// The rope is rebalanced when its depth exceeds this multiple of the depth of a
+// balanced tree with the same number of leaves.
+const REBALANCE_FACTOR: usize = 4;
 
 // A Rope, based on an unbalanced binary tree. The rope is somewhat special in
 // that it tracks positions in the source text. So when locating a position in
@@ -32,10 +38,27 @@ pub struct Rope {
     // FIXME: Allocation is very dumb at the moment, we always add another
     // buffer for every inserted string and we never resuse or collect old
     // memory
-    storage: Vec<Vec<u8>>
+    //
+    // Buffers may be shared with clones and snapshots of the rope, so they
+    // must be copied before their text is overwritten (see `unshare_storage`).
+    storage: Vec<Rc<Vec<u8>>>,
+    // Inserted text is copied into the leaf at the insertion point, rather than
+    // creating a new leaf, if the combined text is shorter than this. Zero
+    // (the default) disables coalescing.
+    coalesce_threshold: usize,
+}
+
+// The state of a Rope at some point, which it can be restored to. A snapshot
+// shares the rope's text, but has its own copy of the tree.
+#[derive(Clone)]
+pub struct RopeSnapshot {
+    root: Node,
+    len: usize,
+    storage: Vec<Rc<Vec<u8>>>,
 }
 
 // A view over a portion of a Rope. Analagous to string slices (`str`);
+#[derive(Clone)]
 pub struct RopeSlice<'rope> {
     // All nodes which make up the slice, in order.
     nodes: Vec<&'rope Lnode>,
@@ -43,9 +66,12 @@ pub struct RopeSlice<'rope> {
     start: usize,
     // The length of text in the last node.
     len: usize,
+    // The offset of the start of the slice in the rope.
+    offset: usize,
 }
 
-// An iterator over the chars in a rope.
+// An iterator over the chars in a rope, yielding each char along with its
+// byte offset. Chars which are split between leaves are reassembled.
 pub struct RopeChars<'rope> {
     data: RopeSlice<'rope>,
     cur_node: usize,
@@ -53,6 +79,34 @@ pub struct RopeChars<'rope> {
     abs_byte: usize,
 }
 
+// An iterator over the bytes in a rope slice.
+pub struct RopeBytes<'rope> {
+    data: RopeSlice<'rope>,
+    // Index of the current node in the slice.
+    cur_node: usize,
+    // Byte in the part of the current node covered by the slice.
+    cur_byte: usize,
+    // The number of bytes left to yield.
+    remaining: usize,
+}
+
+// An iterator over the text of each leaf in a rope, in order.
+pub struct RopeLeaves<'rope> {
+    data: RopeSlice<'rope>,
+    cur_node: usize,
+    // Bytes at the start of the following nodes which have already been
+    // yielded as part of a char split between leaves.
+    skip: usize,
+}
+
+// An iterator over the lines in a rope.
+pub struct RopeLines<'rope> {
+    rope: &'rope Rope,
+    bytes: RopeBytes<'rope>,
+    // The offset of the start of the next line.
+    start: usize,
+}
+
 
 impl_rope!(Rope);
 
@@ -63,6 +117,7 @@ impl Rope {
             root: Node::empty_inner(),
             len: 0,
             storage: vec![],
+            coalesce_threshold: 0,
         }
     }
 
@@ -76,27 +131,95 @@ impl Rope {
     }
 
     pub fn insert(&mut self, start: usize, text: String) {
+        self.insert_bytes(start, text.into_bytes());
+    }
+
+    // Inserts text, which must be valid UTF-8, using it as the storage for the
+    // new leaf.
+    fn insert_bytes(&mut self, start: usize, text: Vec<u8>) {
+        assert!(start <= self.len,
+                "insert index {} out of bounds for rope of length {}",
+                start,
+                self.len);
+        if self.coalesce(start, &text) {
+            return;
+        }
         self.insert_inner(start,
                           text,
                           |this, node| this.root.insert(node, start))
     }
 
+    // Like `insert`, but returns the range of the rope which now holds text,
+    // e.g., so that a renderer knows what to repaint.
+    pub fn insert_tracked(&mut self, start: usize, text: String) -> Range<usize> {
+        let len = text.len();
+        self.insert(start, text);
+        start..start + len
+    }
+
+    // Applies a batch of edits, e.g., from a language server. The offsets in
+    // each edit refer to the text before any of the edits are applied, so the
+    // order of edits does not matter, except that text inserted at the same
+    // offset is inserted in the order of edits. Panics if edits overlap.
+    pub fn apply_edits(&mut self, edits: &[Edit]) {
+        for edit in edit_order(edits, self.len) {
+            match *edit {
+                Edit::Insert { at, ref text } => self.insert_copy(at, text),
+                Edit::Remove { start, end } => self.remove(start, end),
+                Edit::Replace { at, ref text } => self.replace_str(at, text),
+            }
+        }
+    }
+
+    // Sets the length below which leaves are coalesced with inserted text, so
+    // that lots of small inserts don't create lots of tiny leaves. The leaf is
+    // copied for each coalesced insert, so this should be small. Zero disables
+    // coalescing.
+    pub fn set_coalesce_threshold(&mut self, threshold: usize) {
+        self.coalesce_threshold = threshold;
+    }
+
+    // Copies text into the leaf where it would be inserted, if the result is
+    // shorter than the coalesce threshold. Returns whether text was inserted.
+    fn coalesce(&mut self, start: usize, text: &[u8]) -> bool {
+        if text.is_empty() || text.len() >= self.coalesce_threshold {
+            return false;
+        }
+
+        let threshold = self.coalesce_threshold;
+        let storage = &mut self.storage;
+        let added = self.root.update_leaf(start, &mut |leaf, offset| {
+            if leaf.len + text.len() >= threshold {
+                return 0;
+            }
+
+            let old = unsafe { ::std::slice::from_raw_parts(leaf.text, leaf.len) };
+            let mut buf = Vec::with_capacity(leaf.len + text.len());
+            buf.extend_from_slice(&old[..offset]);
+            buf.extend_from_slice(text);
+            buf.extend_from_slice(&old[offset..]);
+            leaf.text = buf.as_ptr();
+            leaf.len = buf.len();
+            storage.push(Rc::new(buf));
+            text.len()
+        });
+        self.len += added;
+        added > 0
+    }
+
     fn insert_inner<F>(&mut self,
                        start: usize,
-                       text: String,
+                       storage: Vec<u8>,
                        do_insert: F)
         where F: Fn(&mut Rope, Box<Node>) -> NodeAction
     {
-        if text.len() == 0 {
+        if storage.is_empty() {
             return;
         }
 
-        debug_assert!(start <= self.len, "insertion out of bounds of rope");
-
-        let len = text.len();
-        let storage = text.into_bytes();
+        let len = storage.len();
         let new_node = Box::new(Node::new_leaf(&storage[..][0] as *const u8, len));
-        self.storage.push(storage);
+        self.storage.push(Rc::new(storage));
 
         match do_insert(self, new_node) {
             NodeAction::Change(n, adj) => {
@@ -109,9 +232,252 @@ impl Rope {
             _ => panic!("Unexpected action")
         }
         self.len += len;
+
+        // Use the number of inserts as an estimate of the number of leaves,
+        // rather than walking the whole tree.
+        let inserts = self.storage.len();
+        let balanced_depth = mem::size_of::<usize>() * 8 - inserts.leading_zeros() as usize + 1;
+        if self.root.depth_at(start) > REBALANCE_FACTOR * balanced_depth {
+            self.rebalance();
+        }
+    }
+
+    // Appends other to the end of self. This does not copy any text, the
+    // trees and storage of the two ropes are joined.
+    pub fn append(&mut self, mut other: Rope) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = other;
+            return;
+        }
+
+        let root = mem::replace(&mut self.root, Node::empty_inner());
+        self.root = Node::new_inner(Some(Box::new(root.into_content())),
+                                    Some(Box::new(other.root.into_content())),
+                                    self.len);
+        // Moving the buffers does not move the text, so other's leaves are
+        // still valid.
+        self.storage.append(&mut other.storage);
+        self.len += other.len;
+    }
+
+    // Inserts other at at, which must be on a char boundary. Like `append`,
+    // this does not copy other's text, so it is the cheap way to insert a
+    // large amount of text which is already in a rope.
+    pub fn insert_rope(&mut self, at: usize, other: Rope) {
+        // Checked here, before self is taken apart, so that a failed insert
+        // leaves self unchanged.
+        assert!(at <= self.len,
+                "insert index {} out of bounds for rope of length {}",
+                at,
+                self.len);
+        assert!(self.is_char_boundary(at), "insert is not on a char boundary");
+        let threshold = self.coalesce_threshold;
+        let (mut left, right) = mem::take(self).split(at);
+        left.append(other);
+        left.append(right);
+        *self = left;
+        self.coalesce_threshold = threshold;
+    }
+
+    // Keeps only the chars for which f returns true. The retained text is
+    // copied into a new rope with a single leaf.
+    pub fn filter_chars<F: FnMut(char) -> bool>(&mut self, mut f: F) {
+        let text: String = self.chars().map(|(c, _)| c).filter(|&c| f(c)).collect();
+        *self = Rope::from_string(text);
+    }
+
+    // The offsets of the occurrences of from replaced by `replace_all`,
+    // found in one pass over the rope. An empty pattern matches at every
+    // char boundary.
+    fn replace_offsets(&self, from: &str) -> Vec<usize> {
+        if from.is_empty() {
+            let mut matches: Vec<usize> = self.chars().map(|(_, i)| i).collect();
+            matches.push(self.len);
+            matches
+        } else {
+            self.full_slice().find_all(from)
+        }
+    }
+
+    // Replaces every non-overlapping occurrence of from with to, like
+    // `str::replace`. Matches may span leaves. The text between matches is not
+    // copied, the tree is rebuilt from the existing leaves, split around the
+    // matches, and a leaf for each copy of to. The copies share one buffer.
+    pub fn replace_all(&mut self, from: &str, to: &str) {
+        let matches = self.replace_offsets(from);
+        if matches.is_empty() {
+            return;
+        }
+
+        let mut old = vec![];
+        self.root.for_each_leaf(&mut |l| old.push(l.clone()));
+        let new_text = to.repeat(matches.len()).into_bytes();
+
+        let mut leaves = vec![];
+        let mut next = (0, 0);
+        let mut kept = 0;
+        for (i, &start) in matches.iter().enumerate() {
+            push_leaf_range(&old, &mut next, kept..start, &mut leaves);
+            if !to.is_empty() {
+                leaves.push(Lnode { text: new_text[i * to.len()..].as_ptr(), len: to.len() });
+            }
+            kept = start + from.len();
+        }
+        push_leaf_range(&old, &mut next, kept..self.len, &mut leaves);
+
+        if !new_text.is_empty() {
+            self.storage.push(Rc::new(new_text));
+        }
+        // Drop buffers which only held replaced text.
+        let buffers = BufferIndex::new(&self.storage);
+        let mut used = vec![false; self.storage.len()];
+        for l in &leaves {
+            used[buffers.find(l.text)] = true;
+        }
+        let mut used = used.into_iter();
+        self.storage.retain(|_| used.next().unwrap());
+
+        self.len = self.len - matches.len() * from.len() + matches.len() * to.len();
+        self.root = Node::root_from_leaves(&leaves);
+    }
+
+    // Splits the rope in two at `at`, which must be on a char boundary. Text is
+    // not copied, buffers used by leaves on both sides of the split are shared.
+    pub fn split(self, at: usize) -> (Rope, Rope) {
+        assert!(at <= self.len, "split out of bounds of rope");
+        assert!(self.is_char_boundary(at), "split is not on a char boundary");
+
+        let mut left = vec![];
+        let mut right = vec![];
+        let mut offset = 0;
+        self.root.for_each_leaf(&mut |l| {
+            if offset + l.len <= at {
+                left.push(l.clone());
+            } else if offset >= at {
+                right.push(l.clone());
+            } else {
+                let split = at - offset;
+                left.push(Lnode { text: l.text, len: split });
+                right.push(Lnode { text: (l.text as usize + split) as *const u8, len: l.len - split });
+            }
+            offset += l.len;
+        });
+
+        // Which buffers are used by each side.
+        let buffers = BufferIndex::new(&self.storage);
+        let mut in_left = vec![false; self.storage.len()];
+        let mut in_right = vec![false; self.storage.len()];
+        for l in &left {
+            in_left[buffers.find(l.text)] = true;
+        }
+        for l in &right {
+            in_right[buffers.find(l.text)] = true;
+        }
+
+        let mut left_storage = vec![];
+        let mut right_storage = vec![];
+        for (i, buf) in self.storage.into_iter().enumerate() {
+            match (in_left[i], in_right[i]) {
+                (true, true) => {
+                    left_storage.push(buf.clone());
+                    right_storage.push(buf);
+                }
+                (true, false) => left_storage.push(buf),
+                (false, true) => right_storage.push(buf),
+                // Text which has been removed from the rope.
+                (false, false) => {}
+            }
+        }
+
+        (Rope {
+            root: Node::root_from_leaves(&left),
+            len: at,
+            storage: left_storage,
+            coalesce_threshold: self.coalesce_threshold,
+        },
+         Rope {
+            root: Node::root_from_leaves(&right),
+            len: self.len - at,
+            storage: right_storage,
+            coalesce_threshold: self.coalesce_threshold,
+        })
+    }
+
+    // Captures the current state of the rope, so that it can be restored later,
+    // e.g., to undo edits. The text is shared rather than copied, but the tree
+    // is copied, so this takes time and memory proportional to the number of
+    // nodes (see `leaf_count`), like `clone`. Rebalancing first keeps that
+    // down for ropes which have had many edits.
+    pub fn snapshot(&self) -> RopeSnapshot {
+        RopeSnapshot {
+            root: self.root.clone(),
+            len: self.len,
+            storage: self.storage.clone(),
+        }
+    }
+
+    // Reverts the rope to the state captured by snapshot. Any edits made since
+    // are lost. The coalesce threshold is not part of the snapshot.
+    pub fn restore(&mut self, snapshot: RopeSnapshot) {
+        self.root = snapshot.root;
+        self.len = snapshot.len;
+        self.storage = snapshot.storage;
+    }
+
+    // Copies any buffers which are shared with another rope or snapshot, and
+    // points leaves at the copies, so that text can be overwritten in place.
+    fn unshare_storage(&mut self) {
+        let shared: Vec<bool> = self.storage.iter().map(|buf| Rc::strong_count(buf) > 1).collect();
+        if !shared.contains(&true) {
+            return;
+        }
+
+        let old = mem::take(&mut self.storage);
+        self.storage = old.iter()
+                          .zip(&shared)
+                          .map(|(buf, &shared)| if shared { Rc::new((**buf).clone()) } else { buf.clone() })
+                          .collect();
+        let storage = &self.storage;
+        let buffers = BufferIndex::new(&old);
+        self.root.for_each_leaf_mut(&mut |leaf| {
+            let addr = leaf.text as usize;
+            let i = buffers.find(leaf.text);
+            if shared[i] {
+                let start = old[i].as_ptr() as usize;
+                leaf.text = (storage[i].as_ptr() as usize + (addr - start)) as *const u8;
+            }
+        });
+    }
+
+    // The number of heap bytes used by storage, see `mem_usage`. Buffers shared
+    // with clones or snapshots are split evenly between the sharers, so that
+    // the usage of all of them adds up to the memory actually used.
+    fn storage_usage(&self) -> usize {
+        let buffers = self.storage.capacity() * mem::size_of::<Rc<Vec<u8>>>();
+        // Each Rc allocation holds the reference counts and the Vec.
+        let rc_size = 2 * mem::size_of::<usize>() + mem::size_of::<Vec<u8>>();
+        let text: usize = self.storage
+                              .iter()
+                              .map(|s| (rc_size + s.capacity()) / Rc::strong_count(s))
+                              .sum();
+        buffers + text
+    }
+
+    // Rebuilds the tree so that it is balanced. Inserting text is likely to
+    // unbalance the tree, so after lots of inserts it may be worth calling this
+    // to speed up slicing. Ropes are also rebalanced automatically when they
+    // become very unbalanced.
+    pub fn rebalance(&mut self) {
+        let mut leaves = vec![];
+        self.root.for_each_leaf(&mut |l| leaves.push(l.clone()));
+        self.root = Node::root_from_leaves(&leaves);
     }
 
     pub fn remove(&mut self, start: usize, end: usize) {
+        assert!(end <= self.len, "removal out of bounds of rope");
         self.remove_inner(start, end, |this| this.root.remove(start, end))
     }
 }
@@ -122,16 +488,37 @@ impl<'rope> RopeSlice<'rope> {
             nodes: vec![],
             start: 0,
             len: 0,
+            offset: 0,
+        }
+    }
+
+    // The offsets of the non-overlapping occurrences of pat, which must
+    // not be empty, like `str::match_indices`.
+    fn find_all(&self, pat: &str) -> Vec<usize> {
+        let pat = pat.as_bytes();
+        let mut result = vec![];
+        // Matches can't start before the end of the previous match.
+        let mut next = 0;
+        let mut offset = 0;
+        for i in 0..self.nodes.len() {
+            let bytes = self.node_bytes(i);
+            for (j, &b) in bytes.iter().enumerate() {
+                if offset + j >= next && b == pat[0] && self.starts_with_at(i, j, pat) {
+                    result.push(offset + j);
+                    next = offset + j + pat.len();
+                }
+            }
+            offset += bytes.len();
         }
+        result
     }
 
     pub fn iter_chars(self) -> RopeChars<'rope> {
-        let start = self.start;
         RopeChars {
+            cur_byte: self.start,
+            abs_byte: self.offset,
             data: self,
             cur_node: 0,
-            cur_byte: start,
-            abs_byte: start,
         }
     }
 }
@@ -139,36 +526,39 @@ impl<'rope> RopeSlice<'rope> {
 impl<'rope> Iterator for RopeChars<'rope> {
     type Item = (char, usize);
     fn next(&mut self) -> Option<(char, usize)> {
-        if self.cur_node >= self.data.nodes.len() {
-            return None;
-        }
-
-        let byte = self.abs_byte;
-        let node = self.data.nodes[self.cur_node];
+        loop {
+            if self.cur_node >= self.data.nodes.len() {
+                return None;
+            }
 
-        if self.cur_node == self.data.nodes.len() - 1 {
+            let node = self.data.nodes[self.cur_node];
 
-            if self.cur_node == 0 && self.cur_byte > self.data.len + self.data.start {
-                // this is the first & last node
-                // we are at the end of the target slice
-                return None
+            if self.cur_node == self.data.nodes.len() - 1 {
+                // The slice only covers the first `len` bytes of the last node (or
+                // `len` bytes from `start` if it is also the first node).
+                let end = if self.cur_node == 0 {
+                    self.data.start + self.data.len
+                } else {
+                    self.data.len
+                };
+                if self.cur_byte >= end {
+                    // we are at the end of the target slice
+                    return None
+                }
             }
 
-            if self.cur_byte + 1 > node.len - self.data.start {
-                // this is the last node
-                // we are at the end of the target slice
-                return None
+            if self.cur_byte < node.len {
+                break;
             }
-        }
 
-        if self.cur_byte >= node.len {
+            // Skip to the start of the next node.
             self.cur_byte = 0;
             self.cur_node += 1;
-            return self.next();
         }
 
+        let byte = self.abs_byte;
         let result = self.read_char();
-        return Some((result, byte));
+        Some((result, byte))
     }
 }
 
@@ -197,7 +587,13 @@ impl<'rope> RopeChars<'rope> {
     }
 
     fn read_byte(&mut self) -> u8 {
-        let node = self.data.nodes[self.cur_node];
+        let mut node = self.data.nodes[self.cur_node];
+        while self.cur_byte >= node.len {
+            // A multi-byte char may straddle two nodes.
+            self.cur_byte = 0;
+            self.cur_node += 1;
+            node = self.data.nodes[self.cur_node];
+        }
         let addr = node.text as usize + self.cur_byte;
         self.cur_byte += 1;
         self.abs_byte += 1;
@@ -208,6 +604,18 @@ impl<'rope> RopeChars<'rope> {
     }
 }
 
+impl Clone for Rope {
+    // Storage is reference counted, so the clone shares the text buffers and
+    // its leaves point at the same text. Only the tree is copied.
+    fn clone(&self) -> Rope {
+        Rope {
+            root: self.root.clone(),
+            storage: self.storage.clone(),
+            ..*self
+        }
+    }
+}
+
 impl ::std::str::FromStr for Rope {
     type Err = ();
     fn from_str(text: &str) -> Result<Rope, ()> {
@@ -219,67 +627,14 @@ impl ::std::str::FromStr for Rope {
     }
 }
 
-impl<'a> fmt::Display for RopeSlice<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        if self.nodes.len() == 0 {
-            return Ok(());
-        }
-
-        let last_idx = self.nodes.len() - 1;
-        for (i, n) in self.nodes.iter().enumerate() {
-            let mut ptr = n.text;
-            let mut len = n.len;
-            if i == 0 {
-                ptr = (ptr as usize + self.start) as *const u8;
-                len -= self.start;
-            }
-            if i == last_idx {
-                len = self.len;
-            }
-            unsafe {
-                try!(write!(fmt,
-                            "{}",
-                            ::std::str::from_utf8(::std::slice::from_raw_parts(ptr, len)).unwrap()));
-            }
-        }
-        Ok(())
-    }
-}
-
-impl<'a> fmt::Debug for RopeSlice<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        let last_idx = self.nodes.len() - 1;
-        for (i, n) in self.nodes.iter().enumerate() {
-            let mut ptr = n.text;
-            let mut len = n.len;
-            if i == 0 {
-                ptr = (ptr as usize + self.start) as *const u8;
-                len -= self.start;
-            } else {
-                try!(write!(fmt, "|"));
-            }
-            if i == last_idx {
-                len = self.len;
-            }
-            unsafe {
-                try!(write!(fmt,
-                            "\"{}\"",
-                            ::std::str::from_utf8(::std::slice::from_raw_parts(ptr, len)).unwrap()));
-            }
-        }
-        Ok(())
-    }
-}
-
-impl fmt::Display for Rope {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(fmt, "{}", self.root)
-    }
-}
-
+// `{:#?}` prints the tree with one node per line, indented by depth.
 impl fmt::Debug for Rope {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(fmt, "{:?}", self.root)
+        if fmt.alternate() {
+            self.root.fmt_tree(fmt, 0)
+        } else {
+            write!(fmt, "{:?}", self.root)
+        }
     }
 }
 
@@ -330,7 +685,7 @@ impl fmt::Debug for Node {
                 unsafe {
                     write!(fmt,
                            "(\"{}\"; {})",
-                           ::std::str::from_utf8(::std::slice::from_raw_parts(*text, len)).unwrap(),
+                           String::from_utf8_lossy(::std::slice::from_raw_parts(*text, len)),
                            len)
                 }
             }
@@ -357,6 +712,58 @@ struct Lnode {
     len: usize,
 }
 
+// Pushes leaves for the text in range onto out, pointing at the text of
+// leaves, which are all the leaves of a rope in order. Successive calls must
+// use increasing ranges; next is the index and offset of the first leaf which
+// may hold text in the range.
+fn push_leaf_range(leaves: &[Lnode],
+                   next: &mut (usize, usize),
+                   range: Range<usize>,
+                   out: &mut Vec<Lnode>) {
+    let (mut i, mut offset) = *next;
+    while i < leaves.len() && offset < range.end {
+        let leaf = &leaves[i];
+        let start = cmp::max(range.start, offset);
+        let end = cmp::min(range.end, offset + leaf.len);
+        if start < end {
+            out.push(Lnode { text: (leaf.text as usize + start - offset) as *const u8, len: end - start });
+        }
+        if offset + leaf.len > range.end {
+            break;
+        }
+        offset += leaf.len;
+        i += 1;
+    }
+    *next = (i, offset);
+}
+
+// Finds the storage buffer which holds a leaf's text, by binary search on the
+// addresses of the buffers.
+struct BufferIndex {
+    // The start address, length and index in storage of each buffer, sorted by
+    // address.
+    buffers: Vec<(usize, usize, usize)>,
+}
+
+impl BufferIndex {
+    fn new(storage: &[Rc<Vec<u8>>]) -> BufferIndex {
+        let mut buffers: Vec<(usize, usize, usize)> =
+            storage.iter().enumerate().map(|(i, buf)| (buf.as_ptr() as usize, buf.len(), i)).collect();
+        buffers.sort_unstable();
+        BufferIndex { buffers }
+    }
+
+    // The index in storage of the buffer holding text.
+    fn find(&self, text: *const u8) -> usize {
+        let addr = text as usize;
+        let pos = self.buffers.partition_point(|&(start, _, _)| start <= addr);
+        match pos.checked_sub(1).map(|pos| self.buffers[pos]) {
+            Some((start, len, i)) if addr < start + len => i,
+            _ => panic!("leaf text outside of rope storage"),
+        }
+    }
+}
+
 impl Node {
     fn empty_inner() -> Node {
         Node::InnerNode(Inode {
@@ -384,6 +791,76 @@ impl Node {
         })
     }
 
+    // Strips an inner node with no right child, which only makes sense at the
+    // root of a rope.
+    fn into_content(self) -> Node {
+        match self {
+            Node::InnerNode(Inode { left: Some(left), right: None, .. }) => *left,
+            n => n,
+        }
+    }
+
+    // Calls f with the leaf where text inserted at offset would be placed, and
+    // the offset in that leaf. f returns the number of bytes it added to the
+    // leaf, which are added to the weights of the nodes above it.
+    fn update_leaf<F>(&mut self, offset: usize, f: &mut F) -> usize
+        where F: FnMut(&mut Lnode, usize) -> usize
+    {
+        match *self {
+            Node::InnerNode(Inode { ref mut left, ref mut right, ref mut weight }) => {
+                if offset <= *weight {
+                    let added = left.as_mut().map_or(0, |left| left.update_leaf(offset, f));
+                    *weight += added;
+                    added
+                } else {
+                    right.as_mut().map_or(0, |right| right.update_leaf(offset - *weight, f))
+                }
+            }
+            Node::LeafNode(ref mut l) => f(l, offset),
+        }
+    }
+
+    // The number of nodes on the path from self to the leaf containing offset
+    // (or where text inserted at offset would be placed).
+    fn depth_at(&self, offset: usize) -> usize {
+        match *self {
+            Node::InnerNode(Inode { ref left, ref right, weight }) => {
+                let child = if offset <= weight {
+                    left.as_ref().map(|n| n.depth_at(offset))
+                } else {
+                    right.as_ref().map(|n| n.depth_at(offset - weight))
+                };
+                1 + child.unwrap_or(0)
+            }
+            Node::LeafNode(_) => 1,
+        }
+    }
+
+    // Builds a balanced tree from leaves, suitable for the root of a rope.
+    fn root_from_leaves(leaves: &[Lnode]) -> Node {
+        match leaves.len() {
+            0 => Node::empty_inner(),
+            1 => Node::new_inner(Some(Box::new(Node::LeafNode(leaves[0].clone()))),
+                                 None,
+                                 leaves[0].len),
+            _ => Node::from_leaves(leaves),
+        }
+    }
+
+    // Builds a balanced tree from leaves, which must not be empty.
+    fn from_leaves(leaves: &[Lnode]) -> Node {
+        if leaves.len() == 1 {
+            return Node::LeafNode(leaves[0].clone());
+        }
+
+        let (left, right) = leaves.split_at(leaves.len() / 2);
+        let left = Node::from_leaves(left);
+        let weight = left.len();
+        Node::new_inner(Some(Box::new(left)),
+                        Some(Box::new(Node::from_leaves(right))),
+                        weight)
+    }
+
     fn len(&self) -> usize {
         match *self {
             Node::InnerNode(Inode { weight, ref right, .. }) => {
@@ -420,10 +897,10 @@ impl Node {
         }
     }
 
-    fn replace(&mut self, start: usize, new_str: &str) {
+    fn replace(&mut self, start: usize, new_bytes: &[u8]) {
         match *self {
-            Node::InnerNode(ref mut i) => i.replace(start, new_str),
-            Node::LeafNode(ref mut l) => l.replace(start, new_str),
+            Node::InnerNode(ref mut i) => i.replace(start, new_bytes),
+            Node::LeafNode(ref mut l) => l.replace(start, new_bytes),
         }
     }
 }
@@ -472,15 +949,6 @@ impl Inode {
             return NodeAction::Remove;
         }
 
-        if left_action == NodeAction::Remove {
-            return NodeAction::Change(self.right.clone().unwrap(),
-                                      -(self.weight as isize));
-        }
-        if right_action == NodeAction::Remove {
-            return NodeAction::Change(self.left.clone().unwrap(),
-                                      -(self.right.as_ref().map(|n| n.len()).unwrap() as isize));
-        }
-
         let mut total_adj = 0;
         if let NodeAction::Change(ref n, adj) = left_action {
             self.left = Some(n.clone());
@@ -500,6 +968,17 @@ impl Inode {
             total_adj += adj;
         }
 
+        // If one side was removed, replace ourselves with the other side (taking
+        // into account any change to that side made above).
+        if left_action == NodeAction::Remove {
+            return NodeAction::Change(self.right.clone().unwrap(),
+                                      total_adj - self.weight as isize);
+        }
+        if right_action == NodeAction::Remove {
+            return NodeAction::Change(self.left.clone().unwrap(),
+                                      total_adj - self.right.as_ref().map(|n| n.len()).unwrap() as isize);
+        }
+
         return NodeAction::Adjust(total_adj);
     }
 
@@ -563,12 +1042,12 @@ impl Inode {
         }
     }
 
-    fn replace(&mut self, start: usize, new_str: &str) {
-        debug!("Inode::replace: {}, {}, {}", start, new_str, self.weight);
-        let end = start + new_str.len();
+    fn replace(&mut self, start: usize, new_bytes: &[u8]) {
+        debug!("Inode::replace: {}, {:?}, {}", start, new_bytes, self.weight);
+        let end = start + new_bytes.len();
         if start < self.weight {
             if let Some(ref mut left) = self.left {
-                left.replace(start, &new_str[..::std::cmp::min(self.weight-start, new_str.len())]);
+                left.replace(start, &new_bytes[..::std::cmp::min(self.weight-start, new_bytes.len())]);
             } else {
                 panic!();
             }
@@ -580,7 +1059,7 @@ impl Inode {
                 (start - self.weight, 0)
             };
             if let Some(ref mut right) = self.right {
-                right.replace(start, &new_str[offset..]);
+                right.replace(start, &new_bytes[offset..]);
             } else {
                 panic!();
             }
@@ -600,7 +1079,8 @@ impl Lnode {
 
         let old_len = self.len;
         if start == 0 {
-            // Truncate the left of the node.
+            // Truncate the left of the node. This never leaves an empty leaf,
+            // since end < len.
             self.text = (self.text as usize + end) as *const u8;
             self.len = old_len - end;
             let delta = self.len as isize - old_len as isize;
@@ -608,7 +1088,8 @@ impl Lnode {
         }
 
         if end >= self.len {
-            // Truncate the right of the node.
+            // Truncate the right of the node. This never leaves an empty leaf,
+            // since start > 0.
             self.len = start;
             return NodeAction::Adjust(self.len as isize - old_len as isize);
         }
@@ -664,13 +1145,13 @@ impl Lnode {
         slice.len = len;
     }
 
-    fn replace(&mut self, start: usize, new_str: &str) {
-        println!("Lnode::replace: {}, {}, {}", start, new_str, self.len);
-        debug_assert!(start + new_str.bytes().len() <= self.len);
+    fn replace(&mut self, start: usize, new_bytes: &[u8]) {
+        debug!("Lnode::replace: {}, {:?}, {}", start, new_bytes, self.len);
+        debug_assert!(start + new_bytes.len() <= self.len);
 
         let addr = (self.text as usize + start) as *mut u8;
         unsafe {
-            ::std::ptr::copy_nonoverlapping(new_str.as_ptr(), addr, new_str.bytes().len());
+            ::std::ptr::copy_nonoverlapping(new_bytes.as_ptr(), addr, new_bytes.len());
         }
     }
 }
@@ -697,6 +1178,810 @@ mod test {
         assert!(r.to_string() == "Hello world!");
     }
 
+    #[test]
+    fn test_is_empty() {
+        let mut r = Rope::new();
+        assert!(r.is_empty());
+
+        r.push_copy("Hello");
+        assert!(!r.is_empty());
+
+        r.remove(0, 5);
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_from_string_no_copy() {
+        let text = "Hello world!".to_string();
+        let ptr = text.as_ptr();
+        let r = Rope::from_string(text);
+        assert_eq!(r, "Hello world!");
+        assert!(r.len() == 12);
+        // The leaf uses the string's buffer.
+        assert!(r.full_slice().nodes[0].text == ptr);
+    }
+
+    #[test]
+    fn test_from_str() {
+        let r: Rope = "hello".parse().unwrap();
+        assert!(r.len() == 5);
+        assert_eq!(r.full_slice().to_string(), "hello");
+
+        let r: Rope = "".parse().unwrap();
+        assert!(r.len() == 0);
+        assert_eq!(r.full_slice().to_string(), "");
+        assert_eq!(r, "");
+    }
+
+    #[test]
+    fn test_char_len() {
+        let mut r: Rope = "Hello \u{a9} w\u{f6}rld \u{20ac}!".parse().unwrap();
+        r.insert_copy(6, "\u{1f600}");
+        assert!(r.char_len() == 17);
+        assert!(r.char_len() < r.len());
+        assert!(r.char_len() == r.to_string().chars().count());
+        assert!(r.slice(6..12).char_len() == 2);
+
+        // A char split between two nodes.
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+        assert!(r.char_len() == 3);
+
+        assert!(Rope::new().char_len() == 0);
+    }
+
+    #[test]
+    fn test_byte_at_char_at() {
+        let mut r: Rope = "Hello w\u{f6}rld!".parse().unwrap();
+        r.insert_copy(5, " \u{20ac}");
+        r.insert_copy(0, ">");
+
+        let text = r.to_string();
+        let bytes: Vec<u8> = r.full_slice().bytes().collect();
+        for (i, &b) in bytes.iter().enumerate() {
+            assert_eq!(r.byte_at(i), Some(b));
+            assert_eq!(r.char_at(i), text.get(i..).and_then(|s| s.chars().next()));
+        }
+        for (c, i) in r.chars() {
+            assert_eq!(r.char_at(i), Some(c));
+        }
+
+        // Inside a multi-byte char.
+        assert_eq!(r.char_at(8), None);
+        assert_eq!(r.byte_at(r.len()), None);
+        assert_eq!(r.char_at(r.len()), None);
+    }
+
+    #[test]
+    fn test_rebalance() {
+        let mut r = Rope::new();
+        for i in 0..8 {
+            r.push_copy(&i.to_string());
+        }
+        assert!(r.depth() == 9);
+
+        r.rebalance();
+        assert!(r.depth() == 4);
+        assert_eq!(r, "01234567");
+        assert!(r.len() == 8);
+        assert_eq!(r.slice(3..6).to_string(), "345");
+
+        r.insert_copy(4, "-");
+        r.remove(0, 2);
+        assert_eq!(r, "23-4567");
+    }
+
+    #[test]
+    fn test_rebalance_auto() {
+        let mut r = Rope::new();
+        let mut expected = String::new();
+        for i in 0..1000 {
+            r.push_copy(&i.to_string());
+            expected.push_str(&i.to_string());
+        }
+        assert!(r.depth() <= 4 * 11 + 1);
+        assert_eq!(r, &*expected);
+    }
+
+    #[test]
+    fn test_append() {
+        let mut r1: Rope = "Hello world!".parse().unwrap();
+        r1.insert_copy(5, ",");
+        let mut r2: Rope = "Bye".parse().unwrap();
+        r2.push_copy(" now.");
+        r1.append(r2);
+        assert_eq!(r1, "Hello, world!Bye now.");
+        assert!(r1.len() == 21);
+        assert_eq!(r1.full_slice().to_string(), "Hello, world!Bye now.");
+        assert_eq!(r1.slice(10..16).to_string(), "ld!Bye");
+
+        r1.insert_copy(13, " ");
+        r1.remove(0, 5);
+        assert_eq!(r1, ", world! Bye now.");
+
+        let mut r = Rope::new();
+        r.append("Hello".parse().unwrap());
+        r.append(Rope::new());
+        assert_eq!(r, "Hello");
+        assert!(r.len() == 5);
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let mut r = Rope::new();
+        r.set_coalesce_threshold(16);
+        for i in 0..100 {
+            r.insert_char(i, (b'a' + (i % 26) as u8) as char);
+        }
+        assert!(r.leaf_count() < 10);
+        assert!(r.len() == 100);
+        let expected: String = (0..100).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+        assert_eq!(r, &*expected);
+
+        // Inserts into the middle of a leaf, and text too long to coalesce.
+        let mut r: Rope = "Hello".parse().unwrap();
+        r.set_coalesce_threshold(16);
+        r.insert_copy(5, "!");
+        r.insert_copy(2, "\u{a9}");
+        assert!(r.leaf_count() == 1);
+        r.insert_copy(0, " world, hello, world");
+        assert!(r.leaf_count() == 2);
+        assert_eq!(r, " world, hello, worldHe\u{a9}llo!");
+        assert_eq!(r.slice(20..25).to_string(), "He\u{a9}l");
+
+        // Disabled by default.
+        let mut r = Rope::new();
+        for i in 0..10 {
+            r.insert_char(i, 'a');
+        }
+        assert!(r.leaf_count() == 10);
+    }
+
+    #[test]
+    fn test_insert_rope() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.push_copy(" \u{a9}2016");
+        let mut other: Rope = "big ".parse().unwrap();
+        other.push_copy("wide ");
+        let text = other.leaves().next().unwrap().as_ptr();
+
+        r.insert_rope(6, other);
+        assert!(r == "Hello big wide world! \u{a9}2016");
+        assert!(r.len() == 28);
+        assert!(r.verify().is_ok());
+        // The inserted text was not copied.
+        assert!(r.leaves().any(|l| l.as_ptr() == text));
+
+        r.insert_rope(0, "Oh, ".parse().unwrap());
+        let len = r.len();
+        r.insert_rope(len, "!".parse().unwrap());
+        r.insert_rope(3, Rope::new());
+        assert!(r == "Oh, Hello big wide world! \u{a9}2016!");
+        assert!(r.verify().is_ok());
+
+        let mut r = Rope::new();
+        r.set_coalesce_threshold(8);
+        r.insert_rope(0, "abc".parse().unwrap());
+        assert!(r == "abc");
+        assert!(r.coalesce_threshold == 8);
+    }
+
+    #[test]
+    #[should_panic(expected = "insert index 13 out of bounds for rope of length 12")]
+    fn test_insert_rope_out_of_bounds() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_rope(13, "abc".parse().unwrap());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_insert_rope_not_char_boundary() {
+        let mut r: Rope = "\u{a9}".parse().unwrap();
+        r.insert_rope(1, "abc".parse().unwrap());
+    }
+
+    #[test]
+    fn test_filter_chars() {
+        let mut r: Rope = "a1b2\u{a9}3c".parse().unwrap();
+        r.insert_copy(4, "x99y");
+        r.insert_copy(0, "0");
+        assert_eq!(r, "0a1b2x99y\u{a9}3c");
+
+        r.filter_chars(|c| !c.is_ascii_digit());
+        assert_eq!(r, "abxy\u{a9}c");
+        assert!(r.len() == 7);
+        r.insert_copy(2, "!");
+        assert_eq!(r, "ab!xy\u{a9}c");
+
+        r.filter_chars(|_| false);
+        assert!(r.is_empty());
+        r.push_copy("ok");
+        assert_eq!(r, "ok");
+    }
+
+    #[test]
+    fn test_split() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+
+        // At a leaf boundary.
+        let (mut left, mut right) = r.clone().split(6);
+        assert_eq!(left, "Hello,");
+        assert!(left.len() == 6);
+        assert_eq!(right, " world!");
+        assert!(right.len() == 7);
+        left.push_copy("!");
+        right.remove(0, 1);
+        assert_eq!(left, "Hello,!");
+        assert_eq!(right, "world!");
+
+        // Inside a leaf.
+        let (mut left, mut right) = r.clone().split(8);
+        assert_eq!(left, "Hello, w");
+        assert!(left.len() == 8);
+        assert_eq!(right, "orld!");
+        assert!(right.len() == 5);
+        // The halves don't share text.
+        left.replace(7, 'W');
+        right.replace(0, 'O');
+        assert_eq!(left, "Hello, W");
+        assert_eq!(right, "Orld!");
+        assert_eq!(right.slice(1..4).to_string(), "rld");
+
+        let (left, right) = r.clone().split(0);
+        assert!(left.is_empty());
+        assert_eq!(right, "Hello, world!");
+        let (left, right) = r.split(13);
+        assert_eq!(left, "Hello, world!");
+        assert!(right.is_empty());
+    }
+
+    #[test]
+    fn test_split_many_leaves() {
+        let mut r = Rope::new();
+        for i in 0..1000 {
+            r.push_copy(&format!("{} ", i));
+        }
+        let text = r.to_string();
+        let at = text.find("500 ").unwrap() + 1;
+        let (left, right) = r.split(at);
+        assert!(left == text[..at]);
+        assert!(right == text[at..]);
+        assert!(left.verify().is_ok());
+        assert!(right.verify().is_ok());
+        // Only the buffer holding "500 " is shared.
+        assert!(left.storage.len() + right.storage.len() == 1001);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_split_char_boundary() {
+        let r: Rope = "Hello \u{a9}".parse().unwrap();
+        r.split(7);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        r.insert_copy(3, "__");
+
+        // Within a leaf.
+        assert!(r.find("wor") == Some(9));
+        assert!(r.find("He") == Some(0));
+        assert!(r.find("") == Some(0));
+        // Spanning leaves.
+        assert!(r.find("l__l") == Some(2));
+        assert!(r.find("o, w") == Some(6));
+        assert!(r.find("Hel__lo, world!") == Some(0));
+        // Absent.
+        assert!(r.find("xyz").is_none());
+        assert!(r.find("world!!").is_none());
+        assert!(r.find("l__x").is_none());
+
+        let s = r.slice(3..12);
+        assert!(s.find("lo") == Some(2));
+        assert!(s.find("Hel").is_none());
+        assert!(s.find("orld").is_none());
+    }
+
+    #[test]
+    fn test_mem_usage() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        assert!(r.leaf_count() == 1);
+        assert!(r.depth() == 2);
+        assert!(r.mem_usage() >= r.len());
+
+        let mut depth = r.depth();
+        for i in 0..4 {
+            r.push_copy(&i.to_string());
+            assert!(r.depth() == depth + 1);
+            depth = r.depth();
+        }
+        assert!(r.leaf_count() == 5);
+        assert!(r.mem_usage() >= r.len());
+
+        let before = r.mem_usage();
+        r.remove(0, 5);
+        assert!(r.mem_usage() >= r.len());
+        assert!(r.mem_usage() <= before);
+
+        let r = Rope::new();
+        assert!(r.leaf_count() == 0);
+        assert!(r.depth() == 1);
+        assert!(r.mem_usage() == 0);
+    }
+
+    #[test]
+    fn test_mem_usage_shared() {
+        let r: Rope = "Hello world! This text is shared.".parse().unwrap();
+        let before = r.mem_usage();
+        let c = r.clone();
+        let shared = r.mem_usage();
+        assert!(shared < before);
+
+        let snapshot = r.snapshot();
+        assert!(r.mem_usage() < shared);
+        drop(snapshot);
+        drop(c);
+        assert!(r.mem_usage() == before);
+    }
+
+    #[test]
+    fn test_lines_in() {
+        let mut text = String::new();
+        for i in 0..100 {
+            text.push_str(&format!("line {}\n", i));
+        }
+        let mut r: Rope = text.parse().unwrap();
+        r.insert_copy(0, "\n");
+
+        // Line 51 starts at 1 + 10 * 7 + 41 * 8.
+        let start = 399;
+        let lines: Vec<String> = r.lines_in(start + 3..start + 20).map(|l| l.to_string()).collect();
+        assert!(lines == vec!["line 51", "line 52", "line 53"]);
+        let lines: Vec<String> = r.lines_in(start + 3..start + 8).map(|l| l.to_string()).collect();
+        assert!(lines == vec!["line 51"]);
+        let lines: Vec<String> = r.lines_in(start + 8..start + 8).map(|l| l.to_string()).collect();
+        assert!(lines == vec!["line 52"]);
+
+        let lines: Vec<String> = r.lines_in(0..2).map(|l| l.to_string()).collect();
+        assert!(lines == vec!["", "line 0"]);
+        assert!(r.lines_in(r.len()..r.len()).next().is_none());
+        assert!(r.lines_in(0..r.len()).count() == 101);
+    }
+
+    #[test]
+    fn test_default() {
+        let mut r = Rope::default();
+        assert!(r.is_empty());
+        assert_eq!(r, "");
+        r.push_copy("Hello");
+        r.push_copy(" world!");
+        assert_eq!(r, "Hello world!");
+        assert!(r.len() == 12);
+    }
+
+    #[test]
+    fn test_remove_prunes_leaves() {
+        let mut r = Rope::new();
+        for i in 0..200 {
+            r.push_copy(&format!("{:03}", i));
+        }
+        assert!(r.leaf_count() == 200);
+
+        // Remove a few chars at a time from the middle, so leaves are emptied.
+        while r.len() > 30 {
+            let start = r.len() / 2;
+            r.remove(start, start + 5);
+            assert!(r.leaf_count() <= r.len());
+            assert!(r.leaves().all(|l| !l.is_empty()));
+        }
+        assert!(r.leaf_count() <= 11);
+
+        let len = r.len();
+        r.remove(0, len);
+        assert!(r.leaf_count() == 0);
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        let debug = format!("{:?}", r);
+        assert!(debug.contains("\"Hello\"; 5"));
+        assert!(debug.contains("\",\"; 1"));
+        assert!(debug.contains("\" world!\"; 7"));
+
+        let tree = format!("{:#?}", r);
+        assert!(tree.lines().count() == r.root.node_count() + 1);
+        assert!(tree.contains("\n      \"Hello\"; 5\n"));
+        assert!(tree.contains("\n      \",\"; 1\n"));
+        assert!(tree.contains("\n    \" world!\"; 7\n"));
+
+        // A char split between two nodes is written lossily, rather than panicking.
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+        assert!(format!("{:?}", r).contains("\"\u{fffd}b\"; 2"));
+        assert!(format!("{:#?}", r).contains("\"\u{fffd}b\"; 2"));
+    }
+
+    #[test]
+    fn test_bytes_exact_size() {
+        let mut r: Rope = "Hello w\u{f6}rld!".parse().unwrap();
+        r.insert_copy(5, " \u{20ac}");
+        let expected = "Hello \u{20ac} w\u{f6}rld!";
+
+        let mut bytes = r.bytes();
+        assert!(bytes.len() == expected.len());
+        bytes.next();
+        assert!(bytes.len() == expected.len() - 1);
+        let bytes: Vec<u8> = r.bytes().collect();
+        assert!(bytes == expected.as_bytes());
+
+        let slice = r.slice(3..10);
+        assert!(slice.bytes().len() == 7);
+        assert!(slice.bytes().collect::<Vec<_>>() == expected.as_bytes()[3..10]);
+        assert!(Rope::new().bytes().len() == 0);
+    }
+
+    #[test]
+    fn test_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash(r: &Rope) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            r.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let text = "Hello world! ".repeat(50);
+        let r1: Rope = text.parse().unwrap();
+        let mut r2: Rope = text[100..].parse().unwrap();
+        r2.insert_copy(0, &text[3..100]);
+        r2.insert_copy(0, &text[..3]);
+        assert!(r1 == r2);
+        assert!(r1.leaves().count() != r2.leaves().count());
+        assert!(hash(&r1) == hash(&r2));
+
+        r2.replace_str(0, "J");
+        assert!(hash(&r1) != hash(&r2));
+        assert!(hash(&Rope::new()) == hash(&"".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_leaves() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, " \u{a9}");
+        r.insert_copy(0, ">");
+        let leaves: Vec<_> = r.leaves().collect();
+        assert!(leaves.len() == r.full_slice().nodes.len());
+        assert!(leaves.len() == 4);
+        assert!(leaves.concat() == r.to_string());
+        assert!(leaves.iter().all(|l| matches!(*l, Cow::Borrowed(_))));
+
+        // A char split between two nodes is yielded with the first.
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+        let leaves: Vec<_> = r.leaves().collect();
+        assert!(leaves == vec!["a\u{a9}", "b"]);
+
+        assert!(Rope::new().leaves().next().is_none());
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut r: Rope = "one\ntwo\n\nthree\nfour".parse().unwrap();
+        r.insert_copy(5, "w");
+        r.insert_copy(10, "thr\n");
+        r.insert_copy(2, "\u{a9}\n");
+
+        assert_eq!(r, "on\u{a9}\ne\ntwwo\n\nthr\nthree\nfour");
+        let lines: Vec<String> = r.lines().map(|l| l.to_string()).collect();
+        assert!(lines == ["on\u{a9}", "e", "twwo", "", "thr", "three", "four"]);
+
+        let r: Rope = "a\nb\n".parse().unwrap();
+        let lines: Vec<String> = r.lines().map(|l| l.to_string()).collect();
+        assert!(lines == ["a", "b"]);
+
+        let r: Rope = "\n".parse().unwrap();
+        let lines: Vec<String> = r.lines().map(|l| l.to_string()).collect();
+        assert!(lines == [""]);
+
+        let r = Rope::new();
+        assert!(r.lines().next().is_none());
+    }
+
+    #[test]
+    fn test_slice_empty_ranges() {
+        let r = Rope::new();
+        assert!(r.slice(0..0).is_empty());
+        assert!(r.slice(..).is_empty());
+
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        for i in 0..14 {
+            let slice = r.slice(i..i);
+            assert!(slice.is_empty());
+            assert_eq!(slice.to_string(), "");
+        }
+        assert_eq!(r.slice(0..13).to_string(), "Hello, world!");
+    }
+
+    #[test]
+    #[should_panic(expected = "slice 3..2 out of bounds for rope of length 12")]
+    fn test_slice_reversed() {
+        let r: Rope = "Hello world!".parse().unwrap();
+        let (start, end) = (3, 2);
+        r.slice(start..end);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice 13..13 out of bounds for rope of length 12")]
+    fn test_slice_empty_out_of_bounds() {
+        let r: Rope = "Hello world!".parse().unwrap();
+        r.slice(13..13);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds for rope of length 12")]
+    fn test_slice_inclusive_max() {
+        let r: Rope = "Hello world!".parse().unwrap();
+        r.slice(..=usize::MAX);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut r: Rope = "Hello \u{a9}world!".parse().unwrap();
+        r.insert_copy(5, ",");
+
+        assert_eq!(r.get(0..5).unwrap().to_string(), "Hello");
+        assert_eq!(r.get(7..14).unwrap().to_string(), "\u{a9}world");
+        assert_eq!(r.get(..).unwrap().to_string(), "Hello, \u{a9}world!");
+        assert!(r.get(15..15).unwrap().is_empty());
+        // Out of range.
+        assert!(r.get(10..16).is_none());
+        assert!(r.get(16..16).is_none());
+        assert!(r.get(..=usize::MAX).is_none());
+        // Reversed.
+        let (start, end) = (5, 2);
+        assert!(r.get(start..end).is_none());
+        // Not on char boundaries.
+        assert!(r.get(8..10).is_none());
+        assert!(r.get(2..8).is_none());
+
+        assert!(r.is_char_boundary(7));
+        assert!(!r.is_char_boundary(8));
+        assert!(r.is_char_boundary(15));
+        assert!(!r.is_char_boundary(16));
+    }
+
+    #[test]
+    fn test_slice_ranges() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+
+        assert_eq!(r.slice(2..8).to_string(), "llo, w");
+        assert_eq!(r.slice(2..=8).to_string(), "llo, wo");
+        assert_eq!(r.slice(7..).to_string(), "world!");
+        assert_eq!(r.slice(..5).to_string(), "Hello");
+        assert_eq!(r.slice(..=5).to_string(), "Hello,");
+        assert_eq!(r.slice(..).to_string(), "Hello, world!");
+        assert_eq!(r.slice(13..).to_string(), "");
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut r1: Rope = "Hello world!".parse().unwrap();
+        r1.insert_copy(5, ",");
+        let mut r2 = r1.clone();
+        assert_eq!(r2, "Hello, world!");
+
+        r2.remove(0, 5);
+        r2.replace(2, 'W');
+        r2.insert_copy(0, "Big");
+        assert_eq!(r2, "Big, World!");
+        assert!(r2.len() == 11);
+
+        assert_eq!(r1, "Hello, world!");
+        assert!(r1.len() == 13);
+    }
+
+    #[test]
+    fn test_clone_shares_storage() {
+        let mut r1: Rope = "Hello world!".parse().unwrap();
+        let r2 = r1.clone();
+        r1.replace_str(0, "J");
+        assert!(r1 == "Jello world!");
+        assert!(r2 == "Hello world!");
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        r.remove(7, 8);
+        let snapshot = r.snapshot();
+        assert!(r == "Hello, orld!");
+
+        r.insert_copy(0, "Oh, ");
+        r.remove(8, 10);
+        r.replace_str(4, "J");
+        r.push_copy("!!");
+        assert!(r == "Oh, Jell orld!!!");
+
+        r.restore(snapshot.clone());
+        assert!(r == "Hello, orld!");
+        assert!(r.len() == 12);
+        assert_eq!(r.slice(7..).to_string(), "orld!");
+
+        // Overwriting text must not change the snapshot.
+        r.replace_str(0, "J");
+        r.rebalance();
+        r.restore(snapshot.clone());
+        assert!(r == "Hello, orld!");
+
+        // Snapshots outlive changes which drop the rope's buffers.
+        r.filter_chars(|c| c != 'l');
+        assert!(r == "Heo, ord!");
+        r.restore(snapshot);
+        assert!(r == "Hello, orld!");
+        r.insert_copy(7, "w");
+        assert!(r == "Hello, world!");
+    }
+
+    #[test]
+    fn test_common_prefix_suffix_len() {
+        let mut a: Rope = "Hello".parse().unwrap();
+        a.push_copy(" big ");
+        a.push_copy("\u{a9}world!");
+        let mut b: Rope = "Hello sm".parse().unwrap();
+        b.push_copy("all \u{a9}w");
+        b.push_copy("orld!");
+        assert!(a.common_prefix_len(&b) == 6);
+        assert!(a.common_suffix_len(&b) == 9);
+        assert!(b.common_prefix_len(&a) == 6);
+        assert!(b.common_suffix_len(&a) == 9);
+
+        assert!(a.common_prefix_len(&a.clone()) == a.len());
+        assert!(a.common_suffix_len(&a.clone()) == a.len());
+
+        let c: Rope = "Goodbye".parse().unwrap();
+        assert!(a.common_prefix_len(&c) == 0);
+        assert!(a.common_suffix_len(&c) == 0);
+        assert!(a.common_prefix_len(&Rope::new()) == 0);
+        assert!(a.common_suffix_len(&Rope::new()) == 0);
+
+        // Chars which share bytes, but are different.
+        let d: Rope = "\u{e9}".parse().unwrap();
+        let e: Rope = "\u{a9}".parse().unwrap();
+        assert!(d.common_suffix_len(&e) == 0);
+        let f: Rope = "\u{aa}".parse().unwrap();
+        assert!(e.common_prefix_len(&f) == 0);
+
+        // The prefix and suffix overlap.
+        let g: Rope = "aa".parse().unwrap();
+        let h: Rope = "aaa".parse().unwrap();
+        assert!(g.common_prefix_len(&h) == 2);
+        assert!(g.common_suffix_len(&h) == 2);
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        assert!(r.verify().is_ok());
+        r.insert_copy(5, ",");
+        r.push_copy(" \u{a9}");
+        r.remove(1, 3);
+        r.remove(12, 14);
+        assert!(r.verify().is_ok());
+        r.rebalance();
+        assert!(r.verify().is_ok());
+        assert!(Rope::new().verify().is_ok());
+
+        let mut bad = r.clone();
+        bad.len += 1;
+        assert!(bad.verify().is_err());
+
+        let mut bad = r.clone();
+        if let Node::InnerNode(ref mut inode) = bad.root {
+            inode.weight += 1;
+        }
+        assert!(bad.verify().is_err());
+
+        let mut bad = r.clone();
+        bad.root.for_each_leaf_mut(&mut |l| l.len += 1);
+        assert!(bad.verify().is_err());
+    }
+
+    #[test]
+    fn test_len_in() {
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        r.push_copy("\u{20ac}c\u{1F600}");
+        r.push_copy("d");
+        assert!(r.len_in(0..r.len()) == r.len());
+        assert!(r.len_in(1..9) == 8);
+        assert!(r.len_in(4..4) == 0);
+
+        assert!(r.char_len_in(0..r.len()) == r.char_len());
+        assert!(r.char_len_in(0..r.len()) == 7);
+        // Ranges across leaves.
+        assert!(r.char_len_in(1..8) == 4);
+        assert!(r.char_len_in(3..13) == 5);
+        assert!(r.char_len_in(7..13) == 3);
+        assert!(r.char_len_in(5..5) == 0);
+        // Only chars which start in the range are counted.
+        assert!(r.char_len_in(2..5) == 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_len_in_out_of_bounds() {
+        let r: Rope = "abc".parse().unwrap();
+        r.len_in(1..4);
+    }
+
+    #[test]
+    fn test_lines_crlf() {
+        let text = "foo\r\nbar\n\r\nb\raz\r";
+        let mut r: Rope = "foo\r".parse().unwrap();
+        r.push_copy("\nbar\n\r");
+        r.push_copy("\nb\raz\r");
+        let lines: Vec<String> = r.lines_crlf().map(|l| l.to_string()).collect();
+        assert!(lines == text.lines().collect::<Vec<_>>());
+        assert!(lines == vec!["foo", "bar", "", "b\raz\r"]);
+        // Lines are still split on '\n' by default.
+        assert_eq!(r.lines().next().unwrap().to_string(), "foo\r");
+
+        let r: Rope = "\r\n\r\n".parse().unwrap();
+        let lines: Vec<String> = r.lines_crlf().map(|l| l.to_string()).collect();
+        assert!(lines == vec!["", ""]);
+        assert!(Rope::new().lines_crlf().next().is_none());
+    }
+
+    #[test]
+    fn test_eq() {
+        let r1: Rope = "Hello world!".parse().unwrap();
+        let mut r2: Rope = "world".parse().unwrap();
+        r2.insert_copy(0, "Hello ");
+        r2.insert_copy(11, "!");
+        assert_eq!(r1, r2);
+
+        let r3: Rope = "Hello world?".parse().unwrap();
+        assert!(r1 != r3);
+        let r4: Rope = "Hello".parse().unwrap();
+        assert!(r1 != r4);
+    }
+
+    #[test]
+    fn test_eq_str() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        assert_eq!(r, "Hello world!");
+        assert!(r == *"Hello world!");
+        assert!(r != "Hello world?");
+        assert!(r != "Hello");
+
+        r.remove(5, 11);
+        assert_eq!(r, "Hello!");
+        r.insert_copy(5, ", world");
+        assert_eq!(r, "Hello, world!");
+    }
+
+    #[test]
+    fn test_display() {
+        let mut r = Rope::new();
+        r.insert_copy(0, "world");
+        r.insert_copy(0, "Hello ");
+        r.push_copy("!");
+        r.insert_copy(5, ",");
+        assert_eq!(format!("{}", r), "Hello, world!");
+    }
+
     #[test]
     fn test_slice_empty_rope() {
         let r: Rope = Rope::new();
@@ -704,6 +1989,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::cmp_owned)]
     fn test_remove() {
         let mut r: Rope = "Hello world!".parse().unwrap();
         r.remove(0, 10);
@@ -719,6 +2005,160 @@ mod test {
     }
 
     #[test]
+    fn test_remove_len() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.remove(0, 6);
+        assert!(r.len() == 6);
+        assert_eq!(r.full_slice().to_string(), "world!");
+
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.remove(5, 11);
+        assert!(r.len() == 6);
+        assert_eq!(r.full_slice().to_string(), "Hello!");
+
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.remove(5, 12);
+        assert!(r.len() == 5);
+        assert_eq!(r.full_slice().to_string(), "Hello");
+
+        r.remove(0, 5);
+        assert!(r.len() == 0);
+        assert_eq!(r.full_slice().to_string(), "");
+    }
+
+    #[test]
+    fn test_remove_len_multi_leaf() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(11, "?");
+        r.insert_copy(5, ",");
+        r.insert_copy(3, "__");
+        let text = "Hel__lo, world?!";
+        assert_eq!(r, text);
+
+        for start in 0..text.len() {
+            for end in start..text.len() + 1 {
+                let mut r = r.clone();
+                r.remove(start, end);
+                let expected = format!("{}{}", &text[..start], &text[end..]);
+                assert!(r.len() == expected.len());
+                assert_eq!(r, &*expected);
+            }
+        }
+
+        r.remove(0, text.len());
+        assert!(r.is_empty());
+        assert_eq!(r, "");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_remove_out_of_bounds() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.remove(5, 13);
+    }
+
+    #[test]
+    fn test_insert_tracked() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        let range = r.insert_tracked(5, ",".to_owned());
+        assert!(range == (5..6));
+        assert_eq!(r.slice(range).to_string(), ",");
+
+        let range = r.insert_tracked(0, "Oh, ".to_owned());
+        assert!(range == (0..4));
+        let range = r.insert_tracked(r.len(), "!!".to_owned());
+        assert!(range == (17..19));
+        assert_eq!(r.slice(range).to_string(), "!!");
+        let range = r.insert_tracked(3, String::new());
+        assert!(range.is_empty());
+        assert!(r == "Oh, Hello, world!!!");
+    }
+
+    #[test]
+    fn test_apply_edits() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.push_copy(" \u{a9}2015");
+        r.apply_edits(&[Edit::Replace { at: 18, text: "6".to_owned() },
+                        Edit::Insert { at: 5, text: ",".to_owned() },
+                        Edit::Remove { start: 6, end: 11 },
+                        Edit::Insert { at: 6, text: "big ".to_owned() },
+                        Edit::Insert { at: 6, text: "wide ".to_owned() },
+                        Edit::Insert { at: 11, text: "World".to_owned() },
+                        Edit::Insert { at: 0, text: "Oh, ".to_owned() }]);
+        assert!(r == "Oh, Hello, big wide World! \u{a9}2016");
+        assert!(r.verify().is_ok());
+
+        let mut r: Rope = "abc".parse().unwrap();
+        r.apply_edits(&[]);
+        r.apply_edits(&[Edit::Insert { at: 3, text: "d".to_owned() },
+                        Edit::Remove { start: 0, end: 3 }]);
+        assert!(r == "d");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_apply_edits_overlapping() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.apply_edits(&[Edit::Remove { start: 0, end: 5 }, Edit::Insert { at: 3, text: "x".to_owned() }]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_apply_edits_out_of_bounds() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.apply_edits(&[Edit::Remove { start: 10, end: 13 }]);
+    }
+
+    #[test]
+    fn test_insert_at_end() {
+        let mut r: Rope = "Hello".parse().unwrap();
+        r.insert_copy(5, " world");
+        assert_eq!(r, "Hello world");
+        r.insert_copy(11, "!");
+        assert_eq!(r, "Hello world!");
+        r.insert_copy(8, "_");
+        r.insert_copy(13, "?");
+        assert_eq!(r, "Hello wo_rld!?");
+        assert!(r.len() == 14);
+    }
+
+    #[test]
+    #[should_panic(expected = "insert index 6 out of bounds for rope of length 5")]
+    fn test_insert_out_of_bounds() {
+        let mut r: Rope = "Hello".parse().unwrap();
+        r.insert_copy(6, "!");
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut r: Rope = "Hello \u{a9}world!".parse().unwrap();
+        r.insert_copy(5, ",");
+
+        r.truncate(20);
+        assert_eq!(r, "Hello, \u{a9}world!");
+        r.truncate(15);
+        assert_eq!(r, "Hello, \u{a9}world!");
+        // Mid-leaf.
+        r.truncate(12);
+        assert_eq!(r, "Hello, \u{a9}wor");
+        assert!(r.len() == 12);
+        // At a leaf boundary.
+        r.truncate(6);
+        assert_eq!(r, "Hello,");
+        assert!(r.len() == 6);
+        r.truncate(0);
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_truncate_char_boundary() {
+        let mut r: Rope = "Hello \u{a9}".parse().unwrap();
+        r.truncate(7);
+    }
+
+    #[test]
+    #[allow(clippy::cmp_owned)]
     fn test_insert_copy() {
         let mut r: Rope = "Hello world!".parse().unwrap();
         r.insert_copy(0, "foo");
@@ -737,6 +2177,111 @@ mod test {
     }
 
     #[test]
+    fn test_replace() {
+        let mut r: Rope = "Hello \u{a9}!".parse().unwrap();
+        r.replace(0, 'J');
+        r.replace(6, '\u{ae}');
+        r.replace(8, '?');
+        assert_eq!(r, "Jello \u{ae}?");
+        assert!(r.len() == 9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_replace_out_of_bounds() {
+        let mut r: Rope = "Hello".parse().unwrap();
+        r.replace(4, '\u{a9}');
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_replace_str_not_char_boundary() {
+        let mut r: Rope = "Hello \u{a9}!".parse().unwrap();
+        r.replace_str(7, "ab");
+    }
+
+    #[test]
+    fn test_replace_str_split_char() {
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        // Leaves the \u{a9} split between two nodes.
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+        r.replace_str(1, "\u{ae}");
+        assert_eq!(r, "a\u{ae}b");
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let mut r: Rope = "foo bar foo\u{a9}".parse().unwrap();
+        r.replace_all("foo", "baz!");
+        assert_eq!(r, "baz! bar baz!\u{a9}");
+        assert!(r.len() == 15);
+
+        // Matches split between leaves.
+        let mut r: Rope = "ab\u{a9}fo\u{a9}fo".parse().unwrap();
+        r.insert_copy(6, "o");
+        r.push_copy("o");
+        assert_eq!(r, "ab\u{a9}foo\u{a9}foo");
+        r.replace_all("foo", "x");
+        assert_eq!(r, "ab\u{a9}x\u{a9}x");
+        assert!(r.len() == 8);
+        r.replace_all("x\u{a9}", "");
+        assert_eq!(r, "ab\u{a9}x");
+        assert!(r.len() == 5);
+
+        // Matches do not overlap.
+        let mut r: Rope = "aaaaa".parse().unwrap();
+        r.replace_all("aa", "b");
+        assert_eq!(r, "bba");
+
+        let mut r: Rope = "abc".parse().unwrap();
+        r.replace_all("bd", "x");
+        assert_eq!(r, "abc");
+        r.replace_all("", "-");
+        assert_eq!(r, "-a-b-c-");
+
+        // Replacing again after text has been removed.
+        let mut r: Rope = "aXbXcXdXeXf".parse().unwrap();
+        r.replace_all("X", "");
+        r.replace_all("", "-");
+        assert_eq!(r, "-a-b-c-d-e-f-");
+        assert!(r.verify().is_ok());
+    }
+
+    #[test]
+    fn test_replace_all_many_matches() {
+        let text = "foo bar ".repeat(80);
+        let mut r = Rope::from_string(text.clone());
+        r.push_copy("baz");
+        r.replace_all("foo", "x");
+        assert_eq!(r, (text.replace("foo", "x") + "baz")[..]);
+        assert!(r.verify().is_ok());
+        // The text between matches and each replacement are a leaf each.
+        assert!(r.leaf_count() == 2 * 80 + 1);
+        assert!(r.storage.len() == 3);
+
+        r.replace_all(" bar x", "");
+        assert_eq!(r, "x bar baz");
+        assert!(r.verify().is_ok());
+        assert!(r.leaf_count() == 3);
+    }
+
+    #[test]
+    fn test_insert_char() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_char(5, '\u{a9}');
+        assert_eq!(r, "Hello\u{a9} world!");
+        assert!(r.len() == 14);
+        // The leaf's storage holds just the char.
+        assert!(r.storage.last().unwrap().capacity() == 2);
+
+        r.insert_char(0, '>');
+        assert_eq!(r, ">Hello\u{a9} world!");
+        assert!(r.len() == 15);
+    }
+
+    #[test]
+    #[allow(clippy::cmp_owned)]
     fn test_push_copy() {
         let mut r: Rope = "Hello world!".parse().unwrap();
         r.push_copy("foo");
@@ -778,7 +2323,6 @@ mod test {
         assert_eq!(Some((' ', 5)), slice.next());
         assert_eq!(Some(('w', 6)), slice.next());
         assert_eq!(Some(('o', 7)), slice.next());
-        assert_eq!(Some(('r', 8)), slice.next());
         assert_eq!(None, slice.next());
     }
 
@@ -809,7 +2353,6 @@ mod test {
         assert_eq!(Some((' ', 5)), slice.next());
         assert_eq!(Some(('w', 6)), slice.next());
         assert_eq!(Some(('o', 7)), slice.next());
-        assert_eq!(Some(('r', 8)), slice.next());
         assert_eq!(None, slice.next());
     }
 
@@ -822,7 +2365,6 @@ mod test {
         assert_eq!(Some(('o', 4)), slice.next());
         assert_eq!(Some(('\u{a9}', 5)), slice.next());
         assert_eq!(Some(('w', 7)), slice.next());
-        assert_eq!(Some(('o', 8)), slice.next());
         assert_eq!(None, slice.next());
     }
 
@@ -837,7 +2379,6 @@ mod test {
         assert_eq!(Some(('o', 4)), slice.next());
         assert_eq!(Some(('\u{a9}', 5)), slice.next());
         assert_eq!(Some(('w', 7)), slice.next());
-        assert_eq!(Some(('o', 8)), slice.next());
         assert_eq!(None, slice.next());
     }
 
@@ -848,7 +2389,6 @@ mod test {
 
         assert_eq!(Some(('e', 1)), slice.next());
         assert_eq!(Some(('l', 2)), slice.next());
-        assert_eq!(Some(('l', 3)), slice.next());
         assert_eq!(None, slice.next());
     }
 
@@ -858,7 +2398,188 @@ mod test {
         let mut slice = r.slice(0..1).iter_chars();
 
         assert_eq!(Some(('H', 0)), slice.next());
-        assert_eq!(Some(('e', 1)), slice.next());
         assert_eq!(None, slice.next());
     }
+
+    #[test]
+    fn test_slice_to_string() {
+        let mut r: Rope = "Helloworld!".parse().unwrap();
+        r.insert_copy(5, " ");
+
+        assert_eq!(r.slice(1..4).to_string(), "ell");
+        assert_eq!(r.slice(3..8).to_string(), "lo wo");
+        assert_eq!(r.slice(0..12).to_string(), "Hello world!");
+        assert_eq!(r.slice(4..4).to_string(), "");
+        assert_eq!(format!("{:?}", r.slice(3..8)), "\"lo\"|\" \"|\"wo\"");
+        assert_eq!(format!("{:?}", r.slice(4..4)), "");
+
+        // A char split between two nodes.
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+        assert_eq!(r.full_slice().to_string(), "a\u{a9}b");
+    }
+
+    #[test]
+    fn test_slice_len() {
+        let mut r: Rope = "Helloworld!".parse().unwrap();
+        r.insert_copy(5, " ");
+
+        assert_eq!(r.slice(3..3).len(), 0);
+        assert!(r.slice(3..3).is_empty());
+        assert!(r.slice(1..4).len() == 3);
+        assert!(!r.slice(1..4).is_empty());
+        assert!(r.slice(3..8).len() == 5);
+        assert!(r.full_slice().len() == 12);
+
+        let r = Rope::new();
+        assert!(r.full_slice().is_empty());
+    }
+
+    #[test]
+    fn test_slice_write_to() {
+        let mut r: Rope = "Hello \u{a9}world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        r.insert_copy(3, "__");
+
+        for &(start, end) in &[(0, 0), (0, 16), (2, 4), (4, 9), (1, 12), (9, 15)] {
+            let slice = r.slice(start..end);
+            let mut out = vec![];
+            slice.write_to(&mut out).unwrap();
+            assert!(out == slice.to_string().into_bytes());
+        }
+    }
+
+    #[test]
+    fn test_slice_as_str() {
+        let mut r: Rope = "Hello\u{a9}world!".parse().unwrap();
+        assert_eq!(r.slice(1..7).as_str(), Some("ello\u{a9}"));
+        assert_eq!(r.full_slice().as_str(), Some("Hello\u{a9}world!"));
+        assert_eq!(r.slice(3..3).as_str(), Some(""));
+        // Not on a char boundary.
+        assert_eq!(r.slice(1..6).as_str(), None);
+
+        r.insert_copy(5, " ");
+        assert_eq!(r.slice(0..5).as_str(), Some("Hello"));
+        assert_eq!(r.slice(3..8).as_str(), None);
+    }
+
+    #[test]
+    fn test_slice_bytes() {
+        let mut r: Rope = "Helloworld!".parse().unwrap();
+        r.insert_copy(5, " ");
+
+        let bytes: Vec<u8> = r.slice(2..9).bytes().collect();
+        assert_eq!(bytes, b"llo wor");
+        let bytes: Vec<u8> = r.full_slice().bytes().collect();
+        assert_eq!(bytes, b"Hello world!");
+        assert!(r.slice(3..3).bytes().next().is_none());
+    }
+
+    #[test]
+    fn test_slice_chars_split_char() {
+        let mut r: Rope = "a\u{a9}b\u{20ac}".parse().unwrap();
+        // Leaves the \u{a9} split between two nodes.
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+
+        let slice = r.full_slice();
+        let chars: Vec<(char, usize)> = slice.chars().collect();
+        assert_eq!(chars, vec![('a', 0), ('\u{a9}', 1), ('b', 3), ('\u{20ac}', 4)]);
+        let chars: String = slice.iter_chars().map(|(c, _)| c).collect();
+        assert_eq!(chars, "a\u{a9}b\u{20ac}");
+    }
+
+    #[test]
+    fn test_chars_multibyte() {
+        let mut r: Rope = "\u{a9}a\u{20ac}".parse().unwrap();
+        r.insert_copy(3, "\u{1f600}b");
+        r.insert_copy(0, "\u{a9}");
+
+        let chars: Vec<(char, usize)> = r.chars().collect();
+        assert_eq!(chars, vec![('\u{a9}', 0),
+                               ('\u{a9}', 2),
+                               ('a', 4),
+                               ('\u{1f600}', 5),
+                               ('b', 9),
+                               ('\u{20ac}', 10)]);
+    }
+
+    #[test]
+    fn test_chars_offsets() {
+        let mut r: Rope = "H\u{e9}llo w\u{f6}rld!".parse().unwrap();
+        r.insert_copy(7, "\u{20ac}\u{a9}x");
+        r.insert_copy(1, "\u{a9}");
+        r.insert_copy(0, "ab");
+        let s = r.to_string();
+
+        let chars: Vec<(char, usize)> = r.chars().collect();
+        let expected: Vec<(char, usize)> = s.char_indices().map(|(i, c)| (c, i)).collect();
+        assert_eq!(chars, expected);
+
+        for &(start, end) in &[(0, 3), (3, 9), (5, 14), (9, 20), (14, 22)] {
+            let chars: Vec<(char, usize)> = r.slice(start..end).chars().collect();
+            let expected: Vec<(char, usize)> = s[start..end].char_indices()
+                                                            .map(|(i, c)| (c, start + i))
+                                                            .collect();
+            assert_eq!(chars, expected);
+        }
+    }
+
+    #[test]
+    fn test_slice_empty_leaves() {
+        let text = "Hello";
+        let nodes = [Node::new_leaf(text.as_ptr(), 0),
+                     Node::new_leaf((text.as_ptr() as usize + 2) as *const u8, 0)];
+        let leaves = nodes.iter().map(|n| match *n {
+            Node::LeafNode(ref l) => l,
+            _ => unreachable!(),
+        }).collect();
+        let slice = RopeSlice {
+            nodes: leaves,
+            start: 0,
+            len: 0,
+            offset: 0,
+        };
+        assert!(slice.is_empty());
+    }
+
+    #[test]
+    fn test_slice_iter_empty_nodes() {
+        let text = "Hello";
+        let nodes = [Node::new_leaf(text.as_ptr(), 0),
+                     Node::new_leaf(text.as_ptr(), 2),
+                     Node::new_leaf((text.as_ptr() as usize + 2) as *const u8, 0),
+                     Node::new_leaf((text.as_ptr() as usize + 2) as *const u8, 0),
+                     Node::new_leaf((text.as_ptr() as usize + 2) as *const u8, 3),
+                     Node::new_leaf((text.as_ptr() as usize + 5) as *const u8, 0)];
+        let leaves = nodes.iter().map(|n| match *n {
+            Node::LeafNode(ref l) => l,
+            _ => unreachable!(),
+        }).collect();
+        let slice = RopeSlice {
+            nodes: leaves,
+            start: 0,
+            len: 0,
+            offset: 0,
+        };
+
+        let chars: Vec<(char, usize)> = slice.chars().collect();
+        assert_eq!(chars, vec![('H', 0), ('e', 1), ('l', 2), ('l', 3), ('o', 4)]);
+        let bytes: Vec<u8> = slice.bytes().collect();
+        assert_eq!(bytes, b"Hello");
+    }
+
+    #[test]
+    fn test_slice_iter_end_in_last_node() {
+        let mut r: Rope = "Helloworld!".parse().unwrap();
+        r.insert_copy(5, " ");
+
+        let s: String = r.slice(0..8).iter_chars().map(|(c, _)| c).collect();
+        assert_eq!(s, "Hello wo");
+        let s: String = r.slice(6..9).iter_chars().map(|(c, _)| c).collect();
+        assert_eq!(s, "wor");
+        let s: String = r.slice(2..11).iter_chars().map(|(c, _)| c).collect();
+        assert_eq!(s, "llo world");
+    }
 }
@@ -13,13 +13,14 @@
 // docs - mod docs, item docs
 // tests
 // pull out into its own crate
-// impl Default, Extend
+// impl Extend
 // impl DoubleEndedIter and ExactSizeIter for RopeChars
 // better allocation
 // balancing?
 
-use std::fmt;
-use std::ops::Range;
+use std::borrow::Cow;
+use std::{fmt, io, mem};
+use std::ops::{Bound, Range, RangeBounds};
 use util::utf8_char_width;
 
 // A Rope, based on an unbalanced binary tree. The rope is somewhat special in
@@ -38,6 +39,7 @@ pub struct Rope {
 }
 
 // A view over a portion of a Rope. Analagous to string slices (`str`);
+#[derive(Clone)]
 pub struct RopeSlice<'rope> {
     // All nodes which make up the slice, in order.
     nodes: Vec<&'rope Lnode>,
@@ -45,9 +47,12 @@ pub struct RopeSlice<'rope> {
     start: usize,
     // The length of text in the last node.
     len: usize,
+    // The offset of the start of the slice in the rope.
+    offset: usize,
 }
 
-// An iterator over the chars in a rope.
+// An iterator over the chars in a rope, yielding each char along with its
+// byte offset. Chars which are split between leaves are reassembled.
 pub struct RopeChars<'rope> {
     data: RopeSlice<'rope>,
     cur_node: usize,
@@ -55,6 +60,42 @@ pub struct RopeChars<'rope> {
     abs_byte: usize,
 }
 
+// An iterator over the bytes in a rope slice.
+pub struct RopeBytes<'rope> {
+    data: RopeSlice<'rope>,
+    // Index of the current node in the slice.
+    cur_node: usize,
+    // Byte in the part of the current node covered by the slice.
+    cur_byte: usize,
+    // The number of bytes left to yield.
+    remaining: usize,
+}
+
+// An iterator over the chars in a rope, yielding each char along with its byte
+// offset, line (1-based) and column (0-based, in chars). See `line_col`.
+pub struct CharPositions<'rope> {
+    chars: RopeChars<'rope>,
+    line: usize,
+    col: usize,
+}
+
+// An iterator over the text of each leaf in a rope, in order.
+pub struct RopeLeaves<'rope> {
+    data: RopeSlice<'rope>,
+    cur_node: usize,
+    // Bytes at the start of the following nodes which have already been
+    // yielded as part of a char split between leaves.
+    skip: usize,
+}
+
+// An iterator over the lines in a rope.
+pub struct RopeLines<'rope> {
+    rope: &'rope Rope,
+    bytes: RopeBytes<'rope>,
+    // The offset of the start of the next line.
+    start: usize,
+}
+
 impl_rope!(Rope);
 
 impl Rope {
@@ -86,31 +127,35 @@ impl Rope {
     }
 
     pub fn insert(&mut self, start: usize, text: String) {
-        self.insert_inner(start,
-                          text,
-                          |this, node| this.root.insert(node, start, start))
+        self.insert_bytes(start, text.into_bytes());
+    }
+
+    // Inserts text, which must be valid UTF-8, using it as the storage for the
+    // new leaf.
+    fn insert_bytes(&mut self, start: usize, text: Vec<u8>) {
+        assert!(start <= self.len,
+                "insert index {} out of bounds for rope of length {}",
+                start,
+                self.len);
+        self.insert_inner(text, |this, node| this.root.insert(node, start, start))
     }
 
     pub fn src_insert(&mut self, start: usize, text: String) {
-        self.insert_inner(start,
-                          text,
-                          |this, node| this.root.src_insert(node, start, start))
+        assert!(start <= self.src_len,
+                "insert index {} out of bounds for source of length {}",
+                start,
+                self.src_len);
+        self.insert_inner(text.into_bytes(), |this, node| this.root.src_insert(node, start, start))
     }
 
-    fn insert_inner<F>(&mut self,
-                       start: usize,
-                       text: String,
-                       do_insert: F)
+    fn insert_inner<F>(&mut self, storage: Vec<u8>, do_insert: F)
         where F: Fn(&mut Rope, Box<Node>) -> NodeAction
     {
-        if text.len() == 0 {
+        if storage.is_empty() {
             return;
         }
 
-        debug_assert!(start <= self.src_len, "insertion out of bounds of rope");
-
-        let len = text.len();
-        let storage = text.into_bytes();
+        let len = storage.len();
         let new_node = Box::new(Node::new_leaf(&storage[..][0] as *const u8, len, 0));
         self.storage.push(storage);
 
@@ -128,6 +173,7 @@ impl Rope {
     }
 
     pub fn remove(&mut self, start: usize, end: usize) {
+        assert!(end <= self.len, "removal out of bounds of rope");
         self.remove_inner(start, end, |this| this.root.remove(start, end, start))
     }
 
@@ -135,6 +181,95 @@ impl Rope {
         self.remove_inner(start, end, |this| this.root.src_remove(start, end, start))
     }
 
+    // Storage is never shared between SrcRopes, so text can always be
+    // overwritten in place.
+    fn unshare_storage(&mut self) {}
+
+    // The number of heap bytes used by storage, see `mem_usage`.
+    fn storage_usage(&self) -> usize {
+        let buffers = self.storage.capacity() * mem::size_of::<Vec<u8>>();
+        let text: usize = self.storage.iter().map(|s| s.capacity()).sum();
+        buffers + text
+    }
+
+
+    // Returns the line (1-based) and column (0-based) of the byte offset
+    // `byte`. Columns are counted in chars, not bytes. `byte` may be the length
+    // of the rope, i.e., the end of the buffer.
+    pub fn line_col(&self, byte: usize) -> (usize, usize) {
+        assert!(byte <= self.len, "offset out of bounds of rope");
+        let mut line = 1;
+        let mut col = 0;
+        for b in self.slice(0..byte).bytes() {
+            if b == b'\n' {
+                line += 1;
+                col = 0;
+            } else if b & 0xc0 != 0x80 {
+                col += 1;
+            }
+        }
+        (line, col)
+    }
+
+    // Like `chars`, but also yields the line and column of each char, computed
+    // in a single pass.
+    pub fn char_positions(&self) -> CharPositions<'_> {
+        CharPositions {
+            chars: self.chars(),
+            line: 1,
+            col: 0,
+        }
+    }
+
+    // The inverse of `line_col`, returns the byte offset of the given line
+    // (1-based) and column (0-based, in chars). Returns None if there is no such
+    // position in the rope. The column may be the length of the line, i.e., the
+    // position of its newline.
+    pub fn byte_of(&self, line: usize, col: usize) -> Option<usize> {
+        let mut cur_line = 1;
+        let mut cur_col = 0;
+        for (c, offset) in self.chars() {
+            if cur_line == line && cur_col == col {
+                return Some(offset);
+            }
+            if c == '\n' {
+                if cur_line == line {
+                    return None;
+                }
+                cur_line += 1;
+                cur_col = 0;
+            } else {
+                cur_col += 1;
+            }
+        }
+
+        if cur_line == line && cur_col == col {
+            Some(self.len)
+        } else {
+            None
+        }
+    }
+
+    // Maps an offset in the current text to the offset in the source text
+    // where that text originated. Returns None if the text at offset was
+    // inserted after the rope was created (so has no source position), or if
+    // offset is out of bounds. Removals do not change the source offsets of the
+    // remaining text, and text replaced in place keeps the source offset of the
+    // text it overwrote.
+    pub fn src_offset(&self, offset: usize) -> Option<usize> {
+        // The source text is always the first buffer in storage.
+        if self.src_len == 0 {
+            return None;
+        }
+        let src = &self.storage[0];
+        let addr = self.root.text_at(offset)? as usize;
+        let src_start = src.as_ptr() as usize;
+        if addr >= src_start && addr < src_start + src.len() {
+            Some(addr - src_start)
+        } else {
+            None
+        }
+    }
 
     // TODO src_replace
     // TODO src_replace_str
@@ -148,13 +283,22 @@ impl Rope {
     }
 
     pub fn src_slice(&self, Range { start, end }: Range<usize>) -> RopeSlice {
-        debug_assert!(end > start && start <= self.src_len && end <= self.src_len);
+        // As for `slice`, empty ranges anywhere in the source are allowed.
+        assert!(start <= end && end <= self.src_len,
+                "src_slice {}..{} out of bounds for source of length {}",
+                start,
+                end,
+                self.src_len);
         if start == end {
             return RopeSlice::empty();
         }
 
         let mut result = RopeSlice::empty();
         self.root.find_src_slice(start, end, &mut result);
+        if let Some(first) = result.nodes.first() {
+            result.offset = self.root.leaf_offset(first).expect("slice of leaf outside of rope") +
+                            result.start;
+        }
         result
     }
 }
@@ -165,53 +309,71 @@ impl<'rope> RopeSlice<'rope> {
             nodes: vec![],
             start: 0,
             len: 0,
+            offset: 0,
         }
     }
 
     pub fn iter_chars(self) -> RopeChars<'rope> {
-        let start = self.start;
         RopeChars {
+            cur_byte: self.start,
+            abs_byte: self.offset,
             data: self,
             cur_node: 0,
-            cur_byte: start,
-            abs_byte: start,
         }
     }
 }
 
+impl<'rope> Iterator for CharPositions<'rope> {
+    type Item = (char, usize, usize, usize);
+    fn next(&mut self) -> Option<(char, usize, usize, usize)> {
+        let (c, offset) = self.chars.next()?;
+        let result = (c, offset, self.line, self.col);
+        if c == '\n' {
+            self.line += 1;
+            self.col = 0;
+        } else {
+            self.col += 1;
+        }
+        Some(result)
+    }
+}
+
 impl<'rope> Iterator for RopeChars<'rope> {
     type Item = (char, usize);
     fn next(&mut self) -> Option<(char, usize)> {
-        if self.cur_node >= self.data.nodes.len() {
-            return None;
-        }
-
-        let byte = self.abs_byte;
-        let node = self.data.nodes[self.cur_node];
+        loop {
+            if self.cur_node >= self.data.nodes.len() {
+                return None;
+            }
 
-        if self.cur_node == self.data.nodes.len() - 1 {
+            let node = self.data.nodes[self.cur_node];
 
-            if self.cur_node == 0 && self.cur_byte > self.data.len + self.data.start {
-                // this is the first & last node
-                // we are at the end of the target slice
-                return None
+            if self.cur_node == self.data.nodes.len() - 1 {
+                // The slice only covers the first `len` bytes of the last node (or
+                // `len` bytes from `start` if it is also the first node).
+                let end = if self.cur_node == 0 {
+                    self.data.start + self.data.len
+                } else {
+                    self.data.len
+                };
+                if self.cur_byte >= end {
+                    // we are at the end of the target slice
+                    return None
+                }
             }
 
-            if self.cur_byte + 1 > node.len - self.data.start {
-                // this is the last node
-                // we are at the end of the target slice
-                return None
+            if self.cur_byte < node.len {
+                break;
             }
-        }
 
-        if self.cur_byte >= node.len {
+            // Skip to the start of the next node.
             self.cur_byte = 0;
             self.cur_node += 1;
-            return self.next();
         }
 
+        let byte = self.abs_byte;
         let result = self.read_char();
-        return Some((result, byte));
+        Some((result, byte))
     }
 }
 
@@ -240,7 +402,13 @@ impl<'rope> RopeChars<'rope> {
     }
 
     fn read_byte(&mut self) -> u8 {
-        let node = self.data.nodes[self.cur_node];
+        let mut node = self.data.nodes[self.cur_node];
+        while self.cur_byte >= node.len {
+            // A multi-byte char may straddle two nodes.
+            self.cur_byte = 0;
+            self.cur_node += 1;
+            node = self.data.nodes[self.cur_node];
+        }
         let addr = node.text as usize + self.cur_byte;
         self.cur_byte += 1;
         self.abs_byte += 1;
@@ -251,6 +419,33 @@ impl<'rope> RopeChars<'rope> {
     }
 }
 
+impl Clone for Rope {
+    fn clone(&self) -> Rope {
+        // Leaves point into `storage`, so copy the storage and point
+        // each cloned leaf at the same offset in the copy.
+        let storage = self.storage.clone();
+        let mut root = self.root.clone();
+        root.for_each_leaf_mut(&mut |leaf| {
+            let addr = leaf.text as usize;
+            let (old, new) = self.storage
+                                 .iter()
+                                 .zip(storage.iter())
+                                 .find(|&(old, _)| {
+                                     let start = old.as_ptr() as usize;
+                                     addr >= start && addr < start + old.len()
+                                 })
+                                 .expect("leaf text outside of rope storage");
+            leaf.text = (new.as_ptr() as usize + (addr - old.as_ptr() as usize)) as *const u8;
+        });
+
+        Rope {
+            root,
+            storage,
+            ..*self
+        }
+    }
+}
+
 impl ::std::str::FromStr for Rope {
     type Err = ();
     fn from_str(text: &str) -> Result<Rope, ()> {
@@ -263,67 +458,14 @@ impl ::std::str::FromStr for Rope {
     }
 }
 
-impl<'a> fmt::Display for RopeSlice<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        if self.nodes.len() == 0 {
-            return Ok(());
-        }
-
-        let last_idx = self.nodes.len() - 1;
-        for (i, n) in self.nodes.iter().enumerate() {
-            let mut ptr = n.text;
-            let mut len = n.len;
-            if i == 0 {
-                ptr = (ptr as usize + self.start) as *const u8;
-                len -= self.start;
-            }
-            if i == last_idx {
-                len = self.len;
-            }
-            unsafe {
-                try!(write!(fmt,
-                            "{}",
-                            ::std::str::from_utf8(::std::slice::from_raw_parts(ptr, len)).unwrap()));
-            }
-        }
-        Ok(())
-    }
-}
-
-impl<'a> fmt::Debug for RopeSlice<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        let last_idx = self.nodes.len() - 1;
-        for (i, n) in self.nodes.iter().enumerate() {
-            let mut ptr = n.text;
-            let mut len = n.len;
-            if i == 0 {
-                ptr = (ptr as usize + self.start) as *const u8;
-                len -= self.start;
-            } else {
-                try!(write!(fmt, "|"));
-            }
-            if i == last_idx {
-                len = self.len;
-            }
-            unsafe {
-                try!(write!(fmt,
-                            "\"{}\"",
-                            ::std::str::from_utf8(::std::slice::from_raw_parts(ptr, len)).unwrap()));
-            }
-        }
-        Ok(())
-    }
-}
-
-impl fmt::Display for Rope {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(fmt, "{}", self.root)
-    }
-}
-
+// `{:#?}` prints the tree with one node per line, indented by depth.
 impl fmt::Debug for Rope {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(fmt, "{:?}", self.root)
+        if fmt.alternate() {
+            self.root.fmt_tree(fmt, 0)
+        } else {
+            write!(fmt, "{:?}", self.root)
+        }
     }
 }
 
@@ -374,7 +516,7 @@ impl fmt::Debug for Node {
                 unsafe {
                     write!(fmt,
                            "(\"{}\"; {})",
-                           ::std::str::from_utf8(::std::slice::from_raw_parts(*text, len)).unwrap(),
+                           String::from_utf8_lossy(::std::slice::from_raw_parts(*text, len)),
                            len)
                 }
             }
@@ -456,6 +598,24 @@ impl Node {
         }
     }
 
+    // The offset in the rope of `leaf`, which is found by address.
+    fn leaf_offset(&self, leaf: &Lnode) -> Option<usize> {
+        match *self {
+            Node::InnerNode(Inode { ref left, ref right, weight, .. }) => {
+                left.as_ref().and_then(|left| left.leaf_offset(leaf)).or_else(|| {
+                    right.as_ref().and_then(|right| right.leaf_offset(leaf)).map(|o| o + weight)
+                })
+            }
+            Node::LeafNode(ref l) => {
+                if ::std::ptr::eq(l, leaf) {
+                    Some(0)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
     // Most of these methods are just doing dynamic dispatch, TODO use a macro
 
     // precond: start < end
@@ -526,10 +686,10 @@ impl Node {
         }
     }
 
-    fn replace(&mut self, start: usize, new_str: &str) {
+    fn replace(&mut self, start: usize, new_bytes: &[u8]) {
         match *self {
-            Node::InnerNode(ref mut i) => i.replace(start, new_str),
-            Node::LeafNode(ref mut l) => l.replace(start, new_str),
+            Node::InnerNode(ref mut i) => i.replace(start, new_bytes),
+            Node::LeafNode(ref mut l) => l.replace(start, new_bytes),
         }
     }
 
@@ -597,15 +757,6 @@ impl Inode {
             return NodeAction::Remove;
         }
 
-        if left_action == NodeAction::Remove {
-            return NodeAction::Change(self.right.clone().unwrap(),
-                                      -(self.weight as isize));
-        }
-        if right_action == NodeAction::Remove {
-            return NodeAction::Change(self.left.clone().unwrap(),
-                                      -(self.right.as_ref().map(|n| n.len()).unwrap() as isize));
-        }
-
         let mut total_adj = 0;
         if let NodeAction::Change(ref n, adj) = left_action {
             self.left = Some(n.clone());
@@ -625,6 +776,17 @@ impl Inode {
             total_adj += adj;
         }
 
+        // If one side was removed, replace ourselves with the other side (taking
+        // into account any change to that side made above).
+        if left_action == NodeAction::Remove {
+            return NodeAction::Change(self.right.clone().unwrap(),
+                                      total_adj - self.weight as isize);
+        }
+        if right_action == NodeAction::Remove {
+            return NodeAction::Change(self.left.clone().unwrap(),
+                                      total_adj - self.right.as_ref().map(|n| n.len()).unwrap() as isize);
+        }
+
         return NodeAction::Adjust(total_adj);
     }
 
@@ -670,15 +832,6 @@ impl Inode {
             return NodeAction::Remove;
         }
 
-        if left_action == NodeAction::Remove {
-            return NodeAction::Change(self.right.clone().unwrap(),
-                                      -(self.weight as isize));
-        }
-        if right_action == NodeAction::Remove {
-            return NodeAction::Change(self.left.clone().unwrap(),
-                                      -(self.right.as_ref().map(|n| n.len()).unwrap() as isize));
-        }
-
         let mut total_adj = 0;
         if let NodeAction::Change(ref n, adj) = left_action {
             self.left = Some(n.clone());
@@ -698,6 +851,17 @@ impl Inode {
             total_adj += adj;
         }
 
+        // If one side was removed, replace ourselves with the other side (taking
+        // into account any change to that side made above).
+        if left_action == NodeAction::Remove {
+            return NodeAction::Change(self.right.clone().unwrap(),
+                                      total_adj - self.weight as isize);
+        }
+        if right_action == NodeAction::Remove {
+            return NodeAction::Change(self.left.clone().unwrap(),
+                                      total_adj - self.right.as_ref().map(|n| n.len()).unwrap() as isize);
+        }
+
         return NodeAction::Adjust(total_adj);
     }
 
@@ -822,12 +986,12 @@ impl Inode {
         }
     }
 
-    fn replace(&mut self, start: usize, new_str: &str) {
-        debug!("Inode::replace: {}, {}, {}", start, new_str, self.weight);
-        let end = start + new_str.len();
+    fn replace(&mut self, start: usize, new_bytes: &[u8]) {
+        debug!("Inode::replace: {}, {:?}, {}", start, new_bytes, self.weight);
+        let end = start + new_bytes.len();
         if start < self.weight {
             if let Some(ref mut left) = self.left {
-                left.replace(start, &new_str[..::std::cmp::min(self.weight-start, new_str.len())]);
+                left.replace(start, &new_bytes[..::std::cmp::min(self.weight-start, new_bytes.len())]);
             } else {
                 panic!();
             }
@@ -839,7 +1003,7 @@ impl Inode {
                 (start - self.weight, 0)
             };
             if let Some(ref mut right) = self.right {
-                right.replace(start, &new_str[offset..]);
+                right.replace(start, &new_bytes[offset..]);
             } else {
                 panic!();
             }
@@ -924,7 +1088,8 @@ impl Lnode {
 
         let old_len = self.len;
         if start == 0 {
-            // Truncate the left of the node.
+            // Truncate the left of the node. This never leaves an empty leaf,
+            // since end < len.
             self.text = (self.text as usize + end) as *const u8;
             self.len = old_len - end;
             let delta = self.len as isize - old_len as isize;
@@ -933,7 +1098,8 @@ impl Lnode {
         }
 
         if end >= self.len {
-            // Truncate the right of the node.
+            // Truncate the right of the node. This never leaves an empty leaf,
+            // since start > 0.
             self.len = start;
             return NodeAction::Adjust(self.len as isize - old_len as isize);
         }
@@ -999,12 +1165,12 @@ impl Lnode {
         slice.len = len;
     }
 
-    fn replace(&mut self, start: usize, new_str: &str) {
-        debug!("Lnode::replace: {}, {}, {}", start, new_str, self.len);
-        debug_assert!(start + new_str.bytes().len() <= self.len);
+    fn replace(&mut self, start: usize, new_bytes: &[u8]) {
+        debug!("Lnode::replace: {}, {:?}, {}", start, new_bytes, self.len);
+        debug_assert!(start + new_bytes.len() <= self.len);
         let addr = (self.text as usize + start) as *mut u8;
         unsafe {
-            ::std::ptr::copy_nonoverlapping(new_str.as_ptr(), addr, new_str.bytes().len());
+            ::std::ptr::copy_nonoverlapping(new_bytes.as_ptr(), addr, new_bytes.len());
         }
     }
 
@@ -1108,6 +1274,669 @@ mod test {
         assert!(r.to_string() == "Hello world!");
     }
 
+    #[test]
+    fn test_is_empty() {
+        let mut r = Rope::new();
+        assert!(r.is_empty());
+
+        r.push_copy("Hello");
+        assert!(!r.is_empty());
+
+        r.remove(0, 5);
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_from_string_no_copy() {
+        let text = "Hello world!".to_string();
+        let ptr = text.as_ptr();
+        let r = Rope::from_string(text);
+        assert_eq!(r, "Hello world!");
+        assert!(r.len() == 12);
+        // The leaf uses the string's buffer.
+        assert!(r.full_slice().nodes[0].text == ptr);
+    }
+
+    #[test]
+    fn test_from_str() {
+        let r: Rope = "hello".parse().unwrap();
+        assert!(r.len() == 5);
+        assert_eq!(r.full_slice().to_string(), "hello");
+
+        let r: Rope = "".parse().unwrap();
+        assert!(r.len() == 0);
+        assert_eq!(r.full_slice().to_string(), "");
+        assert_eq!(r, "");
+    }
+
+    #[test]
+    fn test_char_len() {
+        let mut r: Rope = "Hello \u{a9} w\u{f6}rld \u{20ac}!".parse().unwrap();
+        r.insert_copy(6, "\u{1f600}");
+        assert!(r.char_len() == 17);
+        assert!(r.char_len() < r.len());
+        assert!(r.char_len() == r.to_string().chars().count());
+        assert!(r.slice(6..12).char_len() == 2);
+
+        // A char split between two nodes.
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+        assert!(r.char_len() == 3);
+
+        assert!(Rope::new().char_len() == 0);
+    }
+
+    #[test]
+    fn test_byte_at_char_at() {
+        let mut r: Rope = "Hello w\u{f6}rld!".parse().unwrap();
+        r.insert_copy(5, " \u{20ac}");
+        r.insert_copy(0, ">");
+
+        let text = r.to_string();
+        let bytes: Vec<u8> = r.full_slice().bytes().collect();
+        for (i, &b) in bytes.iter().enumerate() {
+            assert_eq!(r.byte_at(i), Some(b));
+            assert_eq!(r.char_at(i), text.get(i..).and_then(|s| s.chars().next()));
+        }
+        for (c, i) in r.chars() {
+            assert_eq!(r.char_at(i), Some(c));
+        }
+
+        // Inside a multi-byte char.
+        assert_eq!(r.char_at(8), None);
+        assert_eq!(r.byte_at(r.len()), None);
+        assert_eq!(r.char_at(r.len()), None);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        r.insert_copy(3, "__");
+
+        // Within a leaf.
+        assert!(r.find("wor") == Some(9));
+        assert!(r.find("He") == Some(0));
+        assert!(r.find("") == Some(0));
+        // Spanning leaves.
+        assert!(r.find("l__l") == Some(2));
+        assert!(r.find("o, w") == Some(6));
+        assert!(r.find("Hel__lo, world!") == Some(0));
+        // Absent.
+        assert!(r.find("xyz").is_none());
+        assert!(r.find("world!!").is_none());
+        assert!(r.find("l__x").is_none());
+
+        let s = r.slice(3..12);
+        assert!(s.find("lo") == Some(2));
+        assert!(s.find("Hel").is_none());
+        assert!(s.find("orld").is_none());
+    }
+
+    #[test]
+    fn test_mem_usage() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        assert!(r.leaf_count() == 1);
+        assert!(r.depth() == 2);
+        assert!(r.mem_usage() >= r.len());
+
+        let mut depth = r.depth();
+        for i in 0..4 {
+            r.push_copy(&i.to_string());
+            assert!(r.depth() == depth + 1);
+            depth = r.depth();
+        }
+        assert!(r.leaf_count() == 5);
+        assert!(r.mem_usage() >= r.len());
+
+        let before = r.mem_usage();
+        r.remove(0, 5);
+        assert!(r.mem_usage() >= r.len());
+        assert!(r.mem_usage() <= before);
+
+        let r = Rope::new();
+        assert!(r.leaf_count() == 0);
+        assert!(r.depth() == 1);
+        assert!(r.mem_usage() == 0);
+    }
+
+    #[test]
+    fn test_lines_in() {
+        let mut text = String::new();
+        for i in 0..100 {
+            text.push_str(&format!("line {}\n", i));
+        }
+        let mut r: Rope = text.parse().unwrap();
+        r.insert_copy(0, "\n");
+
+        // Line 51 starts at 1 + 10 * 7 + 41 * 8.
+        let start = 399;
+        let lines: Vec<String> = r.lines_in(start + 3..start + 20).map(|l| l.to_string()).collect();
+        assert!(lines == vec!["line 51", "line 52", "line 53"]);
+        let lines: Vec<String> = r.lines_in(start + 3..start + 8).map(|l| l.to_string()).collect();
+        assert!(lines == vec!["line 51"]);
+        let lines: Vec<String> = r.lines_in(start + 8..start + 8).map(|l| l.to_string()).collect();
+        assert!(lines == vec!["line 52"]);
+
+        let lines: Vec<String> = r.lines_in(0..2).map(|l| l.to_string()).collect();
+        assert!(lines == vec!["", "line 0"]);
+        assert!(r.lines_in(r.len()..r.len()).next().is_none());
+        assert!(r.lines_in(0..r.len()).count() == 101);
+    }
+
+    #[test]
+    fn test_default() {
+        let mut r = Rope::default();
+        assert!(r.is_empty());
+        assert_eq!(r, "");
+        r.push_copy("Hello");
+        r.push_copy(" world!");
+        assert_eq!(r, "Hello world!");
+        assert!(r.len() == 12);
+    }
+
+    #[test]
+    fn test_remove_prunes_leaves() {
+        let mut r = Rope::new();
+        for i in 0..200 {
+            r.push_copy(&format!("{:03}", i));
+        }
+        assert!(r.leaf_count() == 200);
+
+        // Remove a few chars at a time from the middle, so leaves are emptied.
+        while r.len() > 30 {
+            let start = r.len() / 2;
+            r.remove(start, start + 5);
+            assert!(r.leaf_count() <= r.len());
+            assert!(r.leaves().all(|l| !l.is_empty()));
+        }
+        assert!(r.leaf_count() <= 11);
+
+        let len = r.len();
+        r.remove(0, len);
+        assert!(r.leaf_count() == 0);
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        let debug = format!("{:?}", r);
+        assert!(debug.contains("\"Hello\"; 5"));
+        assert!(debug.contains("\",\"; 1"));
+        assert!(debug.contains("\" world!\"; 7"));
+
+        let tree = format!("{:#?}", r);
+        assert!(tree.lines().count() == r.root.node_count() + 1);
+        assert!(tree.contains("\n      \"Hello\"; 5\n"));
+        assert!(tree.contains("\n      \",\"; 1\n"));
+        assert!(tree.contains("\n    \" world!\"; 7\n"));
+
+        // A char split between two nodes is written lossily, rather than panicking.
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+        assert!(format!("{:?}", r).contains("\"\u{fffd}b\"; 2"));
+        assert!(format!("{:#?}", r).contains("\"\u{fffd}b\"; 2"));
+    }
+
+    #[test]
+    fn test_bytes_exact_size() {
+        let mut r: Rope = "Hello w\u{f6}rld!".parse().unwrap();
+        r.insert_copy(5, " \u{20ac}");
+        let expected = "Hello \u{20ac} w\u{f6}rld!";
+
+        let mut bytes = r.bytes();
+        assert!(bytes.len() == expected.len());
+        bytes.next();
+        assert!(bytes.len() == expected.len() - 1);
+        let bytes: Vec<u8> = r.bytes().collect();
+        assert!(bytes == expected.as_bytes());
+
+        let slice = r.slice(3..10);
+        assert!(slice.bytes().len() == 7);
+        assert!(slice.bytes().collect::<Vec<_>>() == expected.as_bytes()[3..10]);
+        assert!(Rope::new().bytes().len() == 0);
+    }
+
+    #[test]
+    fn test_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash(r: &Rope) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            r.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let text = "Hello world! ".repeat(50);
+        let r1: Rope = text.parse().unwrap();
+        let mut r2: Rope = text[100..].parse().unwrap();
+        r2.insert_copy(0, &text[3..100]);
+        r2.insert_copy(0, &text[..3]);
+        assert!(r1 == r2);
+        assert!(r1.leaves().count() != r2.leaves().count());
+        assert!(hash(&r1) == hash(&r2));
+
+        r2.replace_str(0, "J");
+        assert!(hash(&r1) != hash(&r2));
+        assert!(hash(&Rope::new()) == hash(&"".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_leaves() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, " \u{a9}");
+        r.insert_copy(0, ">");
+        let leaves: Vec<_> = r.leaves().collect();
+        assert!(leaves.len() == r.full_slice().nodes.len());
+        assert!(leaves.len() == 4);
+        assert!(leaves.concat() == r.to_string());
+        assert!(leaves.iter().all(|l| matches!(*l, Cow::Borrowed(_))));
+
+        // A char split between two nodes is yielded with the first.
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+        let leaves: Vec<_> = r.leaves().collect();
+        assert!(leaves == vec!["a\u{a9}", "b"]);
+
+        assert!(Rope::new().leaves().next().is_none());
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut r: Rope = "one\ntwo\n\nthree\nfour".parse().unwrap();
+        r.insert_copy(5, "w");
+        r.insert_copy(10, "thr\n");
+        r.insert_copy(2, "\u{a9}\n");
+
+        assert_eq!(r, "on\u{a9}\ne\ntwwo\n\nthr\nthree\nfour");
+        let lines: Vec<String> = r.lines().map(|l| l.to_string()).collect();
+        assert!(lines == ["on\u{a9}", "e", "twwo", "", "thr", "three", "four"]);
+
+        let r: Rope = "a\nb\n".parse().unwrap();
+        let lines: Vec<String> = r.lines().map(|l| l.to_string()).collect();
+        assert!(lines == ["a", "b"]);
+
+        let r: Rope = "\n".parse().unwrap();
+        let lines: Vec<String> = r.lines().map(|l| l.to_string()).collect();
+        assert!(lines == [""]);
+
+        let r = Rope::new();
+        assert!(r.lines().next().is_none());
+    }
+
+    #[test]
+    fn test_char_positions() {
+        let mut r: Rope = "fn main() {\n    \u{a9}\n\n}\u{20ac}".parse().unwrap();
+        r.insert_copy(12, "let x = '\u{1f600}';\n");
+        let positions: Vec<(char, usize, usize, usize)> = r.char_positions().collect();
+        assert!(positions.len() == r.char_len());
+        for (c, offset, line, col) in positions {
+            assert!(r.char_at(offset) == Some(c));
+            assert!(r.line_col(offset) == (line, col));
+        }
+
+        let positions: Vec<_> = r.char_positions().skip(12).take(5).collect();
+        assert!(positions == vec![('l', 12, 2, 0), ('e', 13, 2, 1), ('t', 14, 2, 2), (' ', 15, 2, 3), ('x', 16, 2, 4)]);
+        assert!(Rope::new().char_positions().next().is_none());
+    }
+
+    #[test]
+    fn test_line_col() {
+        let mut r: Rope = "fn main() {\n    \u{a9}\u{a9}x\n}\n".parse().unwrap();
+        r.insert_copy(16, "\nlet");
+
+        assert_eq!(r, "fn main() {\n    \nlet\u{a9}\u{a9}x\n}\n");
+        assert!(r.line_col(0) == (1, 0));
+        assert!(r.line_col(3) == (1, 3));
+        // The newline itself is at the end of its line.
+        assert!(r.line_col(11) == (1, 11));
+        assert!(r.line_col(12) == (2, 0));
+        assert!(r.line_col(16) == (2, 4));
+        assert!(r.line_col(17) == (3, 0));
+        // Multibyte chars count as one column.
+        assert!(r.line_col(20) == (3, 3));
+        assert!(r.line_col(22) == (3, 4));
+        assert!(r.line_col(24) == (3, 5));
+        assert!(r.line_col(25) == (3, 6));
+        assert!(r.line_col(26) == (4, 0));
+        // The end of the buffer.
+        assert!(r.line_col(28) == (5, 0));
+
+        let r = Rope::new();
+        assert!(r.line_col(0) == (1, 0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_line_col_out_of_bounds() {
+        let r: Rope = "Hello".parse().unwrap();
+        r.line_col(6);
+    }
+
+    #[test]
+    fn test_byte_of() {
+        let mut r: Rope = "fn main() {\n    \u{a9}\u{a9}x\n}\n".parse().unwrap();
+        r.insert_copy(16, "\nlet");
+
+        for &b in &[0, 3, 11, 12, 16, 17, 20, 22, 24, 25, 26, 27, 28] {
+            let (line, col) = r.line_col(b);
+            assert!(r.byte_of(line, col) == Some(b));
+        }
+
+        assert!(r.byte_of(1, 12).is_none());
+        assert!(r.byte_of(3, 7).is_none());
+        assert!(r.byte_of(5, 1).is_none());
+        assert!(r.byte_of(6, 0).is_none());
+        assert!(r.byte_of(0, 0).is_none());
+
+        let r = Rope::new();
+        assert!(r.byte_of(1, 0) == Some(0));
+        assert!(r.byte_of(1, 1).is_none());
+    }
+
+    #[test]
+    fn test_src_offset() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ", big");
+        assert_eq!(r, "Hello, big world!");
+        assert!(r.src_offset(0) == Some(0));
+        assert!(r.src_offset(4) == Some(4));
+        for i in 5..10 {
+            assert!(r.src_offset(i).is_none());
+        }
+        assert!(r.src_offset(10) == Some(5));
+        assert!(r.src_offset(11) == Some(6));
+        assert!(r.src_offset(16) == Some(11));
+        assert!(r.src_offset(17).is_none());
+
+        r.remove(12, 14);
+        assert_eq!(r, "Hello, big wld!");
+        assert!(r.src_offset(11) == Some(6));
+        assert!(r.src_offset(12) == Some(9));
+        assert!(r.src_offset(14) == Some(11));
+        assert!(r.src_offset(15).is_none());
+
+        r.replace(0, 'J');
+        assert!(r.src_offset(0) == Some(0));
+
+        let mut r = Rope::new();
+        r.insert_copy(0, "Hello");
+        assert!(r.src_offset(0).is_none());
+    }
+
+    #[test]
+    fn test_slice_empty_ranges() {
+        let r = Rope::new();
+        assert!(r.slice(0..0).is_empty());
+        assert!(r.slice(..).is_empty());
+
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        for i in 0..14 {
+            let slice = r.slice(i..i);
+            assert!(slice.is_empty());
+            assert_eq!(slice.to_string(), "");
+        }
+        assert_eq!(r.slice(0..13).to_string(), "Hello, world!");
+    }
+
+    #[test]
+    #[should_panic(expected = "slice 3..2 out of bounds for rope of length 12")]
+    fn test_slice_reversed() {
+        let r: Rope = "Hello world!".parse().unwrap();
+        let (start, end) = (3, 2);
+        r.slice(start..end);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice 13..13 out of bounds for rope of length 12")]
+    fn test_slice_empty_out_of_bounds() {
+        let r: Rope = "Hello world!".parse().unwrap();
+        r.slice(13..13);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds for rope of length 12")]
+    fn test_slice_inclusive_max() {
+        let r: Rope = "Hello world!".parse().unwrap();
+        r.slice(..=usize::MAX);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut r: Rope = "Hello \u{a9}world!".parse().unwrap();
+        r.insert_copy(5, ",");
+
+        assert_eq!(r.get(0..5).unwrap().to_string(), "Hello");
+        assert_eq!(r.get(7..14).unwrap().to_string(), "\u{a9}world");
+        assert_eq!(r.get(..).unwrap().to_string(), "Hello, \u{a9}world!");
+        assert!(r.get(15..15).unwrap().is_empty());
+        // Out of range.
+        assert!(r.get(10..16).is_none());
+        assert!(r.get(16..16).is_none());
+        assert!(r.get(..=usize::MAX).is_none());
+        // Reversed.
+        let (start, end) = (5, 2);
+        assert!(r.get(start..end).is_none());
+        // Not on char boundaries.
+        assert!(r.get(8..10).is_none());
+        assert!(r.get(2..8).is_none());
+
+        assert!(r.is_char_boundary(7));
+        assert!(!r.is_char_boundary(8));
+        assert!(r.is_char_boundary(15));
+        assert!(!r.is_char_boundary(16));
+    }
+
+    #[test]
+    fn test_slice_ranges() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+
+        assert_eq!(r.slice(2..8).to_string(), "llo, w");
+        assert_eq!(r.slice(2..=8).to_string(), "llo, wo");
+        assert_eq!(r.slice(7..).to_string(), "world!");
+        assert_eq!(r.slice(..5).to_string(), "Hello");
+        assert_eq!(r.slice(..=5).to_string(), "Hello,");
+        assert_eq!(r.slice(..).to_string(), "Hello, world!");
+        assert_eq!(r.slice(13..).to_string(), "");
+    }
+
+    #[test]
+    fn test_src_slice_empty_ranges() {
+        let r = Rope::new();
+        assert!(r.src_slice(0..0).is_empty());
+
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        assert!(r.src_slice(0..0).is_empty());
+        assert!(r.src_slice(12..12).is_empty());
+        assert_eq!(r.src_slice(0..12).to_string(), "Hello, world!");
+    }
+
+    #[test]
+    #[should_panic(expected = "src_slice 13..13 out of bounds for source of length 12")]
+    fn test_src_slice_empty_out_of_bounds() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        r.src_slice(13..13);
+    }
+
+    #[test]
+    fn test_src_slice_chars_offsets() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(5, ", big");
+
+        let chars: Vec<(char, usize)> = r.src_slice(6..9).chars().collect();
+        assert_eq!(chars, vec![('w', 11), ('o', 12), ('r', 13)]);
+    }
+
+    #[test]
+    fn test_clone() {
+        let mut r1: Rope = "Hello world!".parse().unwrap();
+        r1.insert_copy(5, ",");
+        let mut r2 = r1.clone();
+        assert_eq!(r2, "Hello, world!");
+
+        r2.remove(0, 5);
+        r2.replace(2, 'W');
+        r2.insert_copy(0, "Big");
+        assert_eq!(r2, "Big, World!");
+        assert!(r2.len() == 11);
+
+        assert_eq!(r1, "Hello, world!");
+        assert!(r1.len() == 13);
+    }
+
+    #[test]
+    fn test_common_prefix_suffix_len() {
+        let mut a: Rope = "Hello".parse().unwrap();
+        a.push_copy(" big ");
+        a.push_copy("\u{a9}world!");
+        let mut b: Rope = "Hello sm".parse().unwrap();
+        b.push_copy("all \u{a9}w");
+        b.push_copy("orld!");
+        assert!(a.common_prefix_len(&b) == 6);
+        assert!(a.common_suffix_len(&b) == 9);
+        assert!(b.common_prefix_len(&a) == 6);
+        assert!(b.common_suffix_len(&a) == 9);
+
+        assert!(a.common_prefix_len(&a.clone()) == a.len());
+        assert!(a.common_suffix_len(&a.clone()) == a.len());
+
+        let c: Rope = "Goodbye".parse().unwrap();
+        assert!(a.common_prefix_len(&c) == 0);
+        assert!(a.common_suffix_len(&c) == 0);
+        assert!(a.common_prefix_len(&Rope::new()) == 0);
+        assert!(a.common_suffix_len(&Rope::new()) == 0);
+
+        // Chars which share bytes, but are different.
+        let d: Rope = "\u{e9}".parse().unwrap();
+        let e: Rope = "\u{a9}".parse().unwrap();
+        assert!(d.common_suffix_len(&e) == 0);
+        let f: Rope = "\u{aa}".parse().unwrap();
+        assert!(e.common_prefix_len(&f) == 0);
+
+        // The prefix and suffix overlap.
+        let g: Rope = "aa".parse().unwrap();
+        let h: Rope = "aaa".parse().unwrap();
+        assert!(g.common_prefix_len(&h) == 2);
+        assert!(g.common_suffix_len(&h) == 2);
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        assert!(r.verify().is_ok());
+        r.insert_copy(5, ",");
+        r.push_copy(" \u{a9}");
+        r.remove(1, 3);
+        r.remove(12, 14);
+        assert!(r.verify().is_ok());
+        assert!(Rope::new().verify().is_ok());
+
+        let mut bad = r.clone();
+        bad.len += 1;
+        assert!(bad.verify().is_err());
+
+        let mut bad = r.clone();
+        if let Node::InnerNode(ref mut inode) = bad.root {
+            inode.weight += 1;
+        }
+        assert!(bad.verify().is_err());
+
+        let mut bad = r.clone();
+        bad.root.for_each_leaf_mut(&mut |l| l.len += 1);
+        assert!(bad.verify().is_err());
+    }
+
+    #[test]
+    fn test_len_in() {
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        r.push_copy("\u{20ac}c\u{1F600}");
+        r.push_copy("d");
+        assert!(r.len_in(0..r.len()) == r.len());
+        assert!(r.len_in(1..9) == 8);
+        assert!(r.len_in(4..4) == 0);
+
+        assert!(r.char_len_in(0..r.len()) == r.char_len());
+        assert!(r.char_len_in(0..r.len()) == 7);
+        // Ranges across leaves.
+        assert!(r.char_len_in(1..8) == 4);
+        assert!(r.char_len_in(3..13) == 5);
+        assert!(r.char_len_in(7..13) == 3);
+        assert!(r.char_len_in(5..5) == 0);
+        // Only chars which start in the range are counted.
+        assert!(r.char_len_in(2..5) == 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_len_in_out_of_bounds() {
+        let r: Rope = "abc".parse().unwrap();
+        r.len_in(1..4);
+    }
+
+    #[test]
+    fn test_lines_crlf() {
+        let text = "foo\r\nbar\n\r\nb\raz\r";
+        let mut r: Rope = "foo\r".parse().unwrap();
+        r.push_copy("\nbar\n\r");
+        r.push_copy("\nb\raz\r");
+        let lines: Vec<String> = r.lines_crlf().map(|l| l.to_string()).collect();
+        assert!(lines == text.lines().collect::<Vec<_>>());
+        assert!(lines == vec!["foo", "bar", "", "b\raz\r"]);
+        // Lines are still split on '\n' by default.
+        assert_eq!(r.lines().next().unwrap().to_string(), "foo\r");
+
+        let r: Rope = "\r\n\r\n".parse().unwrap();
+        let lines: Vec<String> = r.lines_crlf().map(|l| l.to_string()).collect();
+        assert!(lines == vec!["", ""]);
+        assert!(Rope::new().lines_crlf().next().is_none());
+    }
+
+    #[test]
+    fn test_eq() {
+        let r1: Rope = "Hello world!".parse().unwrap();
+        let mut r2: Rope = "Hello !".parse().unwrap();
+        r2.insert_copy(6, "world");
+        assert_eq!(r1, r2);
+
+        let r3: Rope = "Hello world?".parse().unwrap();
+        assert!(r1 != r3);
+        let r4: Rope = "Hello".parse().unwrap();
+        assert!(r1 != r4);
+    }
+
+    #[test]
+    fn test_eq_str() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        assert_eq!(r, "Hello world!");
+        assert!(r == *"Hello world!");
+        assert!(r != "Hello world?");
+        assert!(r != "Hello");
+
+        r.remove(5, 11);
+        assert_eq!(r, "Hello!");
+        r.insert_copy(5, ", world");
+        assert_eq!(r, "Hello, world!");
+    }
+
+    #[test]
+    fn test_display() {
+        let mut r: Rope = "Helloworld".parse().unwrap();
+        r.insert_copy(5, ", ");
+        r.insert_copy(0, "> ");
+        r.insert_copy(9, "!");
+        assert_eq!(format!("{}", r), "> Hello, !world");
+    }
+
     #[test]
     fn test_slice_empty_rope() {
         let r: Rope = Rope::new();
@@ -1115,6 +1944,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::cmp_owned)]
     fn test_remove() {
         let mut r: Rope = "Hello world!".parse().unwrap();
         r.remove(0, 10);
@@ -1138,6 +1968,108 @@ mod test {
     }
 
     #[test]
+    fn test_remove_len() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.remove(0, 6);
+        assert!(r.len() == 6);
+        assert_eq!(r.full_slice().to_string(), "world!");
+
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.remove(5, 11);
+        assert!(r.len() == 6);
+        assert_eq!(r.full_slice().to_string(), "Hello!");
+
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.remove(5, 12);
+        assert!(r.len() == 5);
+        assert_eq!(r.full_slice().to_string(), "Hello");
+
+        r.remove(0, 5);
+        assert!(r.len() == 0);
+        assert_eq!(r.full_slice().to_string(), "");
+    }
+
+    #[test]
+    fn test_remove_len_multi_leaf() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_copy(11, "?");
+        r.insert_copy(5, ",");
+        r.insert_copy(3, "__");
+        let text = "Hel__lo, world?!";
+        assert_eq!(r, text);
+
+        for start in 0..text.len() {
+            for end in start..text.len() + 1 {
+                let mut r = r.clone();
+                r.remove(start, end);
+                let expected = format!("{}{}", &text[..start], &text[end..]);
+                assert!(r.len() == expected.len());
+                assert_eq!(r, &*expected);
+            }
+        }
+
+        r.remove(0, text.len());
+        assert!(r.is_empty());
+        assert_eq!(r, "");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_remove_out_of_bounds() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.remove(5, 13);
+    }
+
+    #[test]
+    fn test_insert_at_end() {
+        let mut r: Rope = "Hello".parse().unwrap();
+        r.insert_copy(5, " world");
+        assert_eq!(r, "Hello world");
+        r.insert_copy(11, "!");
+        assert_eq!(r, "Hello world!");
+        r.insert_copy(8, "_");
+        r.insert_copy(13, "?");
+        assert_eq!(r, "Hello wo_rld!?");
+        assert!(r.len() == 14);
+    }
+
+    #[test]
+    #[should_panic(expected = "insert index 6 out of bounds for rope of length 5")]
+    fn test_insert_out_of_bounds() {
+        let mut r: Rope = "Hello".parse().unwrap();
+        r.insert_copy(6, "!");
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut r: Rope = "Hello \u{a9}world!".parse().unwrap();
+        r.insert_copy(5, ",");
+
+        r.truncate(20);
+        assert_eq!(r, "Hello, \u{a9}world!");
+        r.truncate(15);
+        assert_eq!(r, "Hello, \u{a9}world!");
+        // Mid-leaf.
+        r.truncate(12);
+        assert_eq!(r, "Hello, \u{a9}wor");
+        assert!(r.len() == 12);
+        // At a leaf boundary.
+        r.truncate(6);
+        assert_eq!(r, "Hello,");
+        assert!(r.len() == 6);
+        r.truncate(0);
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_truncate_char_boundary() {
+        let mut r: Rope = "Hello \u{a9}".parse().unwrap();
+        r.truncate(7);
+    }
+
+    #[test]
+    #[allow(clippy::cmp_owned)]
     fn test_insert_copy() {
         let mut r: Rope = "Hello world!".parse().unwrap();
         r.insert_copy(0, "foo");
@@ -1156,6 +2088,55 @@ mod test {
     }
 
     #[test]
+    fn test_replace() {
+        let mut r: Rope = "Hello \u{a9}!".parse().unwrap();
+        r.replace(0, 'J');
+        r.replace(6, '\u{ae}');
+        r.replace(8, '?');
+        assert_eq!(r, "Jello \u{ae}?");
+        assert!(r.len() == 9);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_replace_out_of_bounds() {
+        let mut r: Rope = "Hello".parse().unwrap();
+        r.replace(4, '\u{a9}');
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_replace_str_not_char_boundary() {
+        let mut r: Rope = "Hello \u{a9}!".parse().unwrap();
+        r.replace_str(7, "ab");
+    }
+
+    #[test]
+    fn test_replace_str_split_char() {
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        // Leaves the \u{a9} split between two nodes.
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+        r.replace_str(1, "\u{ae}");
+        assert_eq!(r, "a\u{ae}b");
+    }
+
+    #[test]
+    fn test_insert_char() {
+        let mut r: Rope = "Hello world!".parse().unwrap();
+        r.insert_char(5, '\u{a9}');
+        assert_eq!(r, "Hello\u{a9} world!");
+        assert!(r.len() == 14);
+        // The leaf's storage holds just the char.
+        assert!(r.storage.last().unwrap().capacity() == 2);
+
+        r.insert_char(0, '>');
+        assert_eq!(r, ">Hello\u{a9} world!");
+        assert!(r.len() == 15);
+    }
+
+    #[test]
+    #[allow(clippy::cmp_owned)]
     fn test_push_copy() {
         let mut r: Rope = "Hello world!".parse().unwrap();
         r.push_copy("foo");
@@ -1237,7 +2218,6 @@ mod test {
         assert_eq!(Some((' ', 5)), slice.next());
         assert_eq!(Some(('w', 6)), slice.next());
         assert_eq!(Some(('o', 7)), slice.next());
-        assert_eq!(Some(('r', 8)), slice.next());
         assert_eq!(None, slice.next());
     }
 
@@ -1268,7 +2248,6 @@ mod test {
         assert_eq!(Some((' ', 5)), slice.next());
         assert_eq!(Some(('w', 6)), slice.next());
         assert_eq!(Some(('o', 7)), slice.next());
-        assert_eq!(Some(('r', 8)), slice.next());
         assert_eq!(None, slice.next());
     }
 
@@ -1281,7 +2260,6 @@ mod test {
         assert_eq!(Some(('o', 4)), slice.next());
         assert_eq!(Some(('\u{a9}', 5)), slice.next());
         assert_eq!(Some(('w', 7)), slice.next());
-        assert_eq!(Some(('o', 8)), slice.next());
         assert_eq!(None, slice.next());
     }
 
@@ -1296,7 +2274,6 @@ mod test {
         assert_eq!(Some(('o', 4)), slice.next());
         assert_eq!(Some(('\u{a9}', 5)), slice.next());
         assert_eq!(Some(('w', 7)), slice.next());
-        assert_eq!(Some(('o', 8)), slice.next());
         assert_eq!(None, slice.next());
     }
 
@@ -1307,7 +2284,6 @@ mod test {
 
         assert_eq!(Some(('e', 1)), slice.next());
         assert_eq!(Some(('l', 2)), slice.next());
-        assert_eq!(Some(('l', 3)), slice.next());
         assert_eq!(None, slice.next());
     }
 
@@ -1317,7 +2293,188 @@ mod test {
         let mut slice = r.slice(0..1).iter_chars();
 
         assert_eq!(Some(('H', 0)), slice.next());
-        assert_eq!(Some(('e', 1)), slice.next());
         assert_eq!(None, slice.next());
     }
+
+    #[test]
+    fn test_slice_to_string() {
+        let mut r: Rope = "Helloworld!".parse().unwrap();
+        r.insert_copy(5, " ");
+
+        assert_eq!(r.slice(1..4).to_string(), "ell");
+        assert_eq!(r.slice(3..8).to_string(), "lo wo");
+        assert_eq!(r.slice(0..12).to_string(), "Hello world!");
+        assert_eq!(r.slice(4..4).to_string(), "");
+        assert_eq!(format!("{:?}", r.slice(3..8)), "\"lo\"|\" \"|\"wo\"");
+        assert_eq!(format!("{:?}", r.slice(4..4)), "");
+
+        // A char split between two nodes.
+        let mut r: Rope = "a\u{a9}b".parse().unwrap();
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+        assert_eq!(r.full_slice().to_string(), "a\u{a9}b");
+    }
+
+    #[test]
+    fn test_slice_len() {
+        let mut r: Rope = "Helloworld!".parse().unwrap();
+        r.insert_copy(5, " ");
+
+        assert_eq!(r.slice(3..3).len(), 0);
+        assert!(r.slice(3..3).is_empty());
+        assert!(r.slice(1..4).len() == 3);
+        assert!(!r.slice(1..4).is_empty());
+        assert!(r.slice(3..8).len() == 5);
+        assert!(r.full_slice().len() == 12);
+
+        let r = Rope::new();
+        assert!(r.full_slice().is_empty());
+    }
+
+    #[test]
+    fn test_slice_write_to() {
+        let mut r: Rope = "Hello \u{a9}world!".parse().unwrap();
+        r.insert_copy(5, ",");
+        r.insert_copy(3, "__");
+
+        for &(start, end) in &[(0, 0), (0, 16), (2, 4), (4, 9), (1, 12), (9, 15)] {
+            let slice = r.slice(start..end);
+            let mut out = vec![];
+            slice.write_to(&mut out).unwrap();
+            assert!(out == slice.to_string().into_bytes());
+        }
+    }
+
+    #[test]
+    fn test_slice_as_str() {
+        let mut r: Rope = "Hello\u{a9}world!".parse().unwrap();
+        assert_eq!(r.slice(1..7).as_str(), Some("ello\u{a9}"));
+        assert_eq!(r.full_slice().as_str(), Some("Hello\u{a9}world!"));
+        assert_eq!(r.slice(3..3).as_str(), Some(""));
+        // Not on a char boundary.
+        assert_eq!(r.slice(1..6).as_str(), None);
+
+        r.insert_copy(5, " ");
+        assert_eq!(r.slice(0..5).as_str(), Some("Hello"));
+        assert_eq!(r.slice(3..8).as_str(), None);
+    }
+
+    #[test]
+    fn test_slice_bytes() {
+        let mut r: Rope = "Helloworld!".parse().unwrap();
+        r.insert_copy(5, " ");
+
+        let bytes: Vec<u8> = r.slice(2..9).bytes().collect();
+        assert_eq!(bytes, b"llo wor");
+        let bytes: Vec<u8> = r.full_slice().bytes().collect();
+        assert_eq!(bytes, b"Hello world!");
+        assert!(r.slice(3..3).bytes().next().is_none());
+    }
+
+    #[test]
+    fn test_slice_chars_split_char() {
+        let mut r: Rope = "a\u{a9}b\u{20ac}".parse().unwrap();
+        // Leaves the \u{a9} split between two nodes.
+        r.insert_copy(2, "x");
+        r.remove(2, 3);
+
+        let slice = r.full_slice();
+        let chars: Vec<(char, usize)> = slice.chars().collect();
+        assert_eq!(chars, vec![('a', 0), ('\u{a9}', 1), ('b', 3), ('\u{20ac}', 4)]);
+        let chars: String = slice.iter_chars().map(|(c, _)| c).collect();
+        assert_eq!(chars, "a\u{a9}b\u{20ac}");
+    }
+
+    #[test]
+    fn test_chars_multibyte() {
+        let mut r: Rope = "\u{a9}a\u{20ac}".parse().unwrap();
+        r.insert_copy(3, "\u{1f600}b");
+        r.insert_copy(0, "\u{a9}");
+
+        let chars: Vec<(char, usize)> = r.chars().collect();
+        assert_eq!(chars, vec![('\u{a9}', 0),
+                               ('\u{a9}', 2),
+                               ('a', 4),
+                               ('\u{1f600}', 5),
+                               ('b', 9),
+                               ('\u{20ac}', 10)]);
+    }
+
+    #[test]
+    fn test_chars_offsets() {
+        let mut r: Rope = "H\u{e9}llo w\u{f6}rld!".parse().unwrap();
+        r.insert_copy(7, "\u{20ac}\u{a9}x");
+        r.insert_copy(1, "\u{a9}");
+        r.insert_copy(0, "ab");
+        let s = r.to_string();
+
+        let chars: Vec<(char, usize)> = r.chars().collect();
+        let expected: Vec<(char, usize)> = s.char_indices().map(|(i, c)| (c, i)).collect();
+        assert_eq!(chars, expected);
+
+        for &(start, end) in &[(0, 3), (3, 9), (5, 14), (9, 20), (14, 22)] {
+            let chars: Vec<(char, usize)> = r.slice(start..end).chars().collect();
+            let expected: Vec<(char, usize)> = s[start..end].char_indices()
+                                                            .map(|(i, c)| (c, start + i))
+                                                            .collect();
+            assert_eq!(chars, expected);
+        }
+    }
+
+    #[test]
+    fn test_slice_empty_leaves() {
+        let text = "Hello";
+        let nodes = [Node::new_leaf(text.as_ptr(), 0, 0),
+                     Node::new_leaf((text.as_ptr() as usize + 2) as *const u8, 0, 0)];
+        let leaves = nodes.iter().map(|n| match *n {
+            Node::LeafNode(ref l) => l,
+            _ => unreachable!(),
+        }).collect();
+        let slice = RopeSlice {
+            nodes: leaves,
+            start: 0,
+            len: 0,
+            offset: 0,
+        };
+        assert!(slice.is_empty());
+    }
+
+    #[test]
+    fn test_slice_iter_empty_nodes() {
+        let text = "Hello";
+        let nodes = [Node::new_leaf(text.as_ptr(), 0, 0),
+                     Node::new_leaf(text.as_ptr(), 2, 0),
+                     Node::new_leaf((text.as_ptr() as usize + 2) as *const u8, 0, 0),
+                     Node::new_leaf((text.as_ptr() as usize + 2) as *const u8, 0, 0),
+                     Node::new_leaf((text.as_ptr() as usize + 2) as *const u8, 3, 0),
+                     Node::new_leaf((text.as_ptr() as usize + 5) as *const u8, 0, 0)];
+        let leaves = nodes.iter().map(|n| match *n {
+            Node::LeafNode(ref l) => l,
+            _ => unreachable!(),
+        }).collect();
+        let slice = RopeSlice {
+            nodes: leaves,
+            start: 0,
+            len: 0,
+            offset: 0,
+        };
+
+        let chars: Vec<(char, usize)> = slice.chars().collect();
+        assert_eq!(chars, vec![('H', 0), ('e', 1), ('l', 2), ('l', 3), ('o', 4)]);
+        let bytes: Vec<u8> = slice.bytes().collect();
+        assert_eq!(bytes, b"Hello");
+    }
+
+    #[test]
+    fn test_slice_iter_end_in_last_node() {
+        let mut r: Rope = "Helloworld!".parse().unwrap();
+        r.insert_copy(5, " ");
+
+        let s: String = r.slice(0..8).iter_chars().map(|(c, _)| c).collect();
+        assert_eq!(s, "Hello wo");
+        let s: String = r.slice(6..9).iter_chars().map(|(c, _)| c).collect();
+        assert_eq!(s, "wor");
+        let s: String = r.slice(2..11).iter_chars().map(|(c, _)| c).collect();
+        assert_eq!(s, "llo world");
+    }
 }
@@ -16,9 +16,14 @@
 // char iterator
 //   chars -> char_indices and flip order of char/index
 
-use std::str::FromStr;
-use std::{cmp, fmt};
-use util::utf8_char_width;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::str::{self, FromStr, Utf8Error};
+use std::{cmp, fmt, io, mem};
+use edit::Edit;
+use util::{edit_order, utf8_char_width};
+#[cfg(feature = "unicode")]
+use unicode_segmentation::UnicodeSegmentation;
 
 const MAX_CAPACITY: usize = 0xffff;
 const INIT_CAPACITY: usize = 0xff;
@@ -29,8 +34,24 @@ pub struct StringBuffer {
     // Optimisation that saves us from walking the whole list of nodes everytime
     // we append a string.
     last: *mut StringNode,
-    // The length of the whole StringBuffer.
+    // The length of the whole StringBuffer. This should not be modified from
+    // outside this module; it will become private.
+    #[deprecated(since = "0.1.3", note = "use `len()` instead")]
     pub len: usize,
+    growth: GrowthPolicy,
+}
+
+// How the capacity of new nodes is chosen when a StringBuffer grows. Larger
+// nodes mean fewer nodes to walk when reading, at the cost of larger (and
+// possibly wasted) allocations. A new node is always large enough for the text
+// which caused it to be allocated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrowthPolicy {
+    // Each node's capacity is `factor` times that of the previous node, up to
+    // `max`.
+    Geometric { factor: usize, max: usize },
+    // Every node has the same capacity.
+    Fixed(usize),
 }
 
 pub struct Chars<'a> {
@@ -40,6 +61,56 @@ pub struct Chars<'a> {
     cur_byte: usize,
     // Byte since start of StringBuffer.
     abs_byte: usize,
+    // The offset at which iteration stops, usually the length of the
+    // StringBuffer.
+    len: usize,
+}
+
+// Like `Chars`, but yields an error for invalid UTF-8 rather than panicking.
+// After an error, iteration continues from the next byte which could start a
+// char.
+pub struct TryChars<'a> {
+    chars: Chars<'a>,
+}
+
+// An iterator over the extended grapheme clusters in a StringBuffer, yielding
+// each grapheme along with its byte offset.
+#[cfg(feature = "unicode")]
+pub struct Graphemes<'a> {
+    nodes: Nodes<'a>,
+    // Text from the current node, prefixed by the last grapheme of the
+    // previous node, since that grapheme may continue into this node.
+    window: String,
+    // The offset of window in the buffer.
+    window_start: usize,
+    // The start of each grapheme in window.
+    starts: Vec<usize>,
+    // Index into starts of the next grapheme to yield.
+    next: usize,
+}
+
+// An iterator over the text of each node in a StringBuffer, in order.
+pub struct Chunks<'a> {
+    nodes: Nodes<'a>,
+}
+
+// A position in a StringBuffer which can be moved forwards and backwards a
+// char at a time.
+pub struct Cursor<'a> {
+    // The nodes from the first to the current node. Nodes only link forwards,
+    // so these are needed to move backwards.
+    nodes: Vec<&'a StringNode>,
+    // The offset of the start of the current node in the buffer.
+    node_start: usize,
+    // Byte in the current node.
+    cur_byte: usize,
+}
+
+// An iterator over the chars removed from a StringBuffer by `drain`.
+pub struct Drain {
+    text: String,
+    // Byte offset of the next char in text.
+    pos: usize,
 }
 
 struct StringNode {
@@ -47,28 +118,251 @@ struct StringNode {
     next: Option<Box<StringNode>>,
 }
 
+// An iterator over the nodes in a StringBuffer.
+struct Nodes<'a> {
+    next: Option<&'a StringNode>,
+}
+
+// The `len` field is only deprecated for users outside this module.
+#[allow(deprecated)]
 impl StringBuffer {
     pub fn new() -> StringBuffer {
         StringBuffer::with_capacity(INIT_CAPACITY)
     }
 
     pub fn with_capacity(capacity: usize) -> StringBuffer {
+        StringBuffer::with_growth_policy(capacity, GrowthPolicy::default())
+    }
+
+    // Creates a buffer whose first node has the given capacity, and where
+    // later nodes are allocated according to growth.
+    pub fn with_growth_policy(capacity: usize, growth: GrowthPolicy) -> StringBuffer {
         let mut result = StringBuffer {
             first: Box::new(StringNode::with_capacity(capacity)),
             last: 0 as *mut StringNode,
             len: 0,
+            growth,
         };
         result.last = &mut *result.first;
         result
     }
 
+    // The length of the buffer in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Joins the parts into a single buffer. The nodes of each part are moved
+    // into the result, so no text is copied.
+    pub fn concat(parts: Vec<StringBuffer>) -> StringBuffer {
+        let mut parts = parts.into_iter();
+        let mut result = match parts.next() {
+            Some(first) => first,
+            None => return StringBuffer::new(),
+        };
+
+        for StringBuffer { first, last, len, .. } in parts {
+            unsafe {
+                (*result.last).next = Some(first);
+            }
+            // Nodes are boxed, so `last` is still valid after moving the part.
+            result.last = last;
+            result.len += len;
+        }
+        result
+    }
+
+    // Pushing may reallocate the data in the last node. Since this requires
+    // `&mut self`, the borrow checker ensures that there are no outstanding
+    // references into the buffer (e.g., a `Chars` iterator) when we do so.
     pub fn push_str(&mut self, text: &str) {
         self.len += text.len();
         unsafe {
             // Safety invariant: the `last` field will only ever point to
             // a node owned by self, and will live until destruction of self.
-            self.last = (&mut *self.last).push_str(text);
+            self.last = (&mut *self.last).push_str(text, self.growth);
+        }
+        debug_assert!(unsafe { (*self.last).next.is_none() }, "`last` is not the last node of the StringBuffer");
+    }
+
+    // Appends bytes without checking that they are valid UTF-8. A char may be
+    // split between calls, and will never be split between nodes.
+    //
+    // Safety: the caller must ensure that the bytes pushed, together with those
+    // pushed before and after, are valid UTF-8 before the buffer is read or
+    // pushed to with `push_str`.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn push_bytes_unchecked(&mut self, bytes: &[u8]) {
+        self.len += bytes.len();
+        // See `push_str` for why `last` is valid.
+        self.last = (&mut *self.last).push_bytes(bytes, self.growth);
+        debug_assert!((*self.last).next.is_none(), "`last` is not the last node of the StringBuffer");
+    }
+
+    // Pushes each of lines followed by a newline, except the last line if
+    // trailing_newline is false. Room for all the lines is reserved up front,
+    // so they are pushed into at most one new node.
+    pub fn push_lines(&mut self, lines: &[&str], trailing_newline: bool) {
+        if lines.is_empty() {
+            return;
+        }
+
+        let newlines = if trailing_newline { lines.len() } else { lines.len() - 1 };
+        let len = lines.iter().map(|l| l.len()).sum::<usize>() + newlines;
+        self.reserve_exact(len);
+        for (i, line) in lines.iter().enumerate() {
+            self.push_str(line);
+            if i < newlines {
+                self.push_str("\n");
+            }
+        }
+    }
+
+    // Inserts text at byte offset at, which must be on a char boundary. Text
+    // inserted at the end of the buffer is pushed, otherwise it is inserted
+    // into the node containing at, which may need to reallocate.
+    pub fn insert_str(&mut self, at: usize, text: &str) {
+        assert!(at <= self.len, "insert index {} out of bounds for buffer of length {}", at, self.len);
+        if at == self.len {
+            self.push_str(text);
+            return;
+        }
+
+        let mut offset = 0;
+        let mut node = &mut *self.first;
+        while at >= offset + node.data.len() {
+            offset += node.data.len();
+            node = node.next.as_deref_mut().expect("offset is in bounds, but not in any node");
+        }
+        assert!(node.data.is_char_boundary(at - offset), "insert index {} is not on a char boundary", at);
+        node.data.insert_str(at - offset, text);
+        self.len += text.len();
+    }
+
+    // Appends text n times, e.g., for indentation. Room for all the text is
+    // reserved up front, so it is pushed into at most one new node.
+    pub fn push_repeated(&mut self, text: &str, n: usize) {
+        let len = text.len().checked_mul(n).expect("capacity overflow");
+        self.reserve_exact(len);
+        for _ in 0..n {
+            self.push_str(text);
+        }
+    }
+
+    // Removes the text in the given range, whose ends must be on char
+    // boundaries. Nodes which are left empty are unlinked.
+    pub fn remove_range(&mut self, Range { start, end }: Range<usize>) {
+        assert!(start <= end && end <= self.len,
+                "range {}..{} out of bounds for buffer of length {}",
+                start,
+                end,
+                self.len);
+        assert!(self.is_char_boundary(start) && self.is_char_boundary(end),
+                "range {}..{} is not on char boundaries",
+                start,
+                end);
+        if start == end {
+            return;
+        }
+
+        let mut offset = 0;
+        self.for_each_node_mut(|n| {
+            let node_len = n.data.len();
+            if start < offset + node_len && end > offset {
+                let from = start.saturating_sub(offset);
+                let to = cmp::min(end - offset, node_len);
+                n.data.drain(from..to);
+            }
+            offset += node_len;
+        });
+        self.len -= end - start;
+        self.remove_empty_nodes();
+    }
+
+    // Removes the text in the given range, like `String::drain`, returning an
+    // iterator over the removed chars. The range's ends must be on char
+    // boundaries. The removed text is copied out of the buffer and removed
+    // before returning, so the iterator need not be consumed.
+    pub fn drain(&mut self, range: Range<usize>) -> Drain {
+        // If range is invalid, `remove_range` panics with a better message.
+        let text = self.get(range.clone()).unwrap_or_default();
+        self.remove_range(range);
+        Drain {
+            text,
+            pos: 0,
+        }
+    }
+
+    // Replaces the text in the given range with text, like
+    // `String::replace_range`. The range's ends must be on char boundaries.
+    pub fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        let start = range.start;
+        self.remove_range(range);
+        self.insert_str(start, text);
+    }
+
+    // Applies a batch of edits. As for `Rope::apply_edits`, the offsets in each
+    // edit refer to the text before any of the edits are applied, and text
+    // inserted at the same offset is inserted in the order of edits. Edits are
+    // applied from the end of the buffer backwards, so each edit leaves the
+    // offsets of those still to be applied unchanged. Replacements may change
+    // the length of the text. Panics if edits overlap.
+    pub fn apply_edits(&mut self, edits: &[Edit]) {
+        for edit in edit_order(edits, self.len) {
+            match *edit {
+                Edit::Insert { at, ref text } => self.insert_str(at, text),
+                Edit::Remove { start, end } => self.remove_range(start..end),
+                Edit::Replace { at, ref text } => self.replace_range(at..at + text.len(), text),
+            }
+        }
+    }
+
+    // Inserts ch at byte offset at, without allocating a String for it.
+    pub fn insert_char(&mut self, at: usize, ch: char) {
+        self.insert_str(at, ch.encode_utf8(&mut [0; 4]));
+    }
+
+    // Appends all the text read from r, returning the number of bytes read.
+    // Chars which are split between reads are handled correctly. Returns an
+    // error if the text is not valid UTF-8, in which case any text read before
+    // the invalid data will have been appended.
+    pub fn append_from_reader<R: io::Read>(&mut self, r: &mut R) -> io::Result<usize> {
+        let mut buf = [0; 0x1000];
+        // The number of bytes at the start of buf which are the beginning of a
+        // char we have not finished reading.
+        let mut pending = 0;
+        let mut total = 0;
+        loop {
+            let n = match r.read(&mut buf[pending..]) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            total += n;
+
+            let end = pending + n;
+            let (valid, is_invalid) = match ::std::str::from_utf8(&buf[..end]) {
+                Ok(text) => (text.len(), false),
+                // Either invalid data, or an incomplete char at the end of buf.
+                Err(e) => (e.valid_up_to(), e.error_len().is_some()),
+            };
+            self.push_str(unsafe { ::std::str::from_utf8_unchecked(&buf[..valid]) });
+            if is_invalid {
+                return Err(invalid_utf8());
+            }
+            buf.copy_within(valid..end, 0);
+            pending = end - valid;
+        }
+
+        if pending > 0 {
+            return Err(invalid_utf8());
         }
+        Ok(total)
     }
 
     // Returns the number of characters from the start of the last line in the
@@ -88,6 +382,38 @@ impl StringBuffer {
         }
     }
 
+    // Like `cur_offset`, but for text with "\r\n" line endings. A '\r' at the
+    // end of the buffer is not counted, since it is the start of a line ending
+    // whose '\n' has not been pushed yet. `cur_offset` and the other line
+    // methods only treat '\n' as a line ending, and so count any '\r'.
+    pub fn cur_offset_crlf(&self) -> usize {
+        let offset = self.cur_offset();
+        if offset > 0 && self.matches_at(self.len - 1, "\r") {
+            offset - 1
+        } else {
+            offset
+        }
+    }
+
+    // The length in bytes of the first line, not including its newline. This
+    // is the whole buffer if there are no newlines.
+    pub fn first_line_len(&self) -> usize {
+        let mut offset = 0;
+        for node in self.nodes() {
+            if let Some(i) = node.data.find('\n') {
+                return offset + i;
+            }
+            offset += node.data.len();
+        }
+        self.len
+    }
+
+    // The length in bytes of the last line, i.e., the text after the last
+    // newline. This is the same as `cur_offset`.
+    pub fn last_line_len(&self) -> usize {
+        self.cur_offset()
+    }
+
     pub fn truncate(&mut self, new_len: usize) {
         if new_len >= self.len {
             return;
@@ -108,8 +434,527 @@ impl StringBuffer {
         self.len = new_len;
     }
 
+    // Replaces every non-overlapping occurrence of from with to, like
+    // `str::replace`. Matches may span nodes. The buffer is rebuilt (keeping its
+    // growth policy) if there are any matches.
+    pub fn replace_all(&mut self, from: &str, to: &str) {
+        let mut result = StringBuffer::with_growth_policy(self.first.data.capacity(), self.growth);
+        if from.is_empty() {
+            for (c, _) in self.chars() {
+                result.push_str(to);
+                result.push_str(c.encode_utf8(&mut [0; 4]));
+            }
+            result.push_str(to);
+            *self = result;
+            return;
+        }
+
+        let mut matched = false;
+        // Text at the end of the previous chunks which could be the start of a
+        // match.
+        let mut carry = String::new();
+        for chunk in self.chunks() {
+            let mut window = mem::take(&mut carry);
+            window.push_str(chunk);
+
+            let mut pos = 0;
+            for (i, _) in window.match_indices(from) {
+                result.push_str(&window[pos..i]);
+                result.push_str(to);
+                pos = i + from.len();
+                matched = true;
+            }
+
+            // A match can't start before keep, since there would be enough
+            // text in the window to find it.
+            let mut keep = cmp::max(pos, window.len().saturating_sub(from.len() - 1));
+            while !window.is_char_boundary(keep) {
+                keep -= 1;
+            }
+            result.push_str(&window[pos..keep]);
+            carry = window.split_off(keep);
+        }
+        result.push_str(&carry);
+
+        if matched {
+            *self = result;
+        }
+    }
+
+    // Removes trailing ASCII whitespace, which may span several nodes.
+    pub fn trim_end_in_place(&mut self) {
+        // The length of the whitespace run at the end of the nodes seen so far.
+        let mut trailing = 0;
+        for node in self.nodes() {
+            let bytes = node.data.as_bytes();
+            let n = bytes.iter().rev().take_while(|b| b.is_ascii_whitespace()).count();
+            if n == bytes.len() {
+                trailing += n;
+            } else {
+                trailing = n;
+            }
+        }
+
+        let new_len = self.len - trailing;
+        self.truncate(new_len);
+    }
+
+    // Removes leading ASCII whitespace, which may span several nodes. Nodes
+    // which are left empty are unlinked.
+    pub fn trim_start_in_place(&mut self) {
+        let mut leading = 0;
+        for node in self.nodes() {
+            let n = node.data.bytes().take_while(|b| b.is_ascii_whitespace()).count();
+            leading += n;
+            if n < node.data.len() {
+                break;
+            }
+        }
+        self.remove_front(leading);
+    }
+
+    // Removes prefix from the start of the buffer, if it is there. Returns
+    // whether the prefix was removed.
+    pub fn strip_prefix(&mut self, prefix: &str) -> bool {
+        if !self.matches_at(0, prefix) {
+            return false;
+        }
+        self.remove_front(prefix.len());
+        true
+    }
+
+    // Removes suffix from the end of the buffer, if it is there. Returns
+    // whether the suffix was removed.
+    pub fn strip_suffix(&mut self, suffix: &str) -> bool {
+        if suffix.len() > self.len || !self.matches_at(self.len - suffix.len(), suffix) {
+            return false;
+        }
+        let new_len = self.len - suffix.len();
+        self.truncate(new_len);
+        true
+    }
+
+    // Removes the first n bytes, which must end on a char boundary. Nodes which
+    // are left empty are unlinked.
+    fn remove_front(&mut self, mut n: usize) {
+        loop {
+            let k = cmp::min(n, self.first.data.len());
+            self.first.data.drain(..k);
+            self.len -= k;
+            n -= k;
+
+            if (n == 0 && !self.first.data.is_empty()) || self.first.next.is_none() {
+                break;
+            }
+            // The first node is never `last` here, so `last` remains valid.
+            self.first = self.first.next.take().unwrap();
+        }
+        debug_assert!(unsafe { (*self.last).next.is_none() });
+    }
+
+    // Whether the buffer contains text starting at byte offset.
+    fn matches_at(&self, mut offset: usize, text: &str) -> bool {
+        if offset + text.len() > self.len {
+            return false;
+        }
+
+        let mut text = text.as_bytes();
+        for node in self.nodes() {
+            if text.is_empty() {
+                break;
+            }
+            let data = node.data.as_bytes();
+            if offset >= data.len() {
+                offset -= data.len();
+                continue;
+            }
+            let n = cmp::min(data.len() - offset, text.len());
+            if data[offset..offset + n] != text[..n] {
+                return false;
+            }
+            text = &text[n..];
+            offset = 0;
+        }
+        text.is_empty()
+    }
+
+    // Keeps only the chars for which f returns true, like `String::retain`.
+    pub fn retain<F: FnMut(char) -> bool>(&mut self, mut f: F) {
+        self.for_each_node_mut(|n| n.data.retain(&mut f));
+
+        self.len = self.nodes().map(|n| n.data.len()).sum();
+        self.remove_empty_nodes();
+    }
+
+    // Unlinks any empty nodes (other than the first) and fixes up `last`.
+    fn remove_empty_nodes(&mut self) {
+        let mut node = &mut *self.first;
+        loop {
+            while node.next.as_ref().is_some_and(|n| n.data.is_empty()) {
+                let empty = node.next.take().unwrap();
+                node.next = empty.next;
+            }
+            if node.next.is_none() {
+                break;
+            }
+            node = node.next.as_deref_mut().unwrap();
+        }
+        self.last = node;
+    }
+
+    // The total capacity of all nodes in the buffer.
+    pub fn capacity(&self) -> usize {
+        self.nodes().map(|n| n.data.capacity()).sum()
+    }
+
+    // Frees any unused capacity in every node, e.g., after truncating. Note
+    // that this means the next push will need to allocate a new node.
+    pub fn shrink_to_fit(&mut self) {
+        self.for_each_node_mut(|n| n.data.shrink_to_fit());
+    }
+
+    // Ensures that additional more bytes can be pushed without allocating. If
+    // the last node does not have room, then a node with exactly enough capacity
+    // is added, rather than following the growth policy. As when pushing, the
+    // unused capacity of the previous last node is freed.
+    pub fn reserve_exact(&mut self, additional: usize) {
+        // See `push_str` for why `last` is valid.
+        let last = unsafe { &mut *self.last };
+        if last.data.capacity() - last.data.len() >= additional {
+            return;
+        }
+        if last.data.is_empty() {
+            last.data.reserve_exact(additional);
+            return;
+        }
+
+        last.data.shrink_to_fit();
+        last.next = Some(Box::new(StringNode::with_capacity(additional)));
+        self.last = last.next.as_deref_mut().unwrap();
+    }
+
+    // Converts the buffer to ASCII lower case in place, see
+    // `str::make_ascii_lowercase`.
+    pub fn make_ascii_lowercase(&mut self) {
+        self.for_each_node_mut(|n| n.data.make_ascii_lowercase());
+    }
+
+    // Converts the buffer to ASCII upper case in place, see
+    // `str::make_ascii_uppercase`.
+    pub fn make_ascii_uppercase(&mut self) {
+        self.for_each_node_mut(|n| n.data.make_ascii_uppercase());
+    }
+
+    // The number of lines in the buffer, counted in the same way as
+    // `str::lines`. I.e., an empty buffer has no lines, and a trailing newline
+    // does not start a new line.
+    pub fn line_count(&self) -> usize {
+        let mut newlines = 0;
+        let mut last_byte = None;
+        for node in self.nodes() {
+            newlines += node.data.bytes().filter(|&b| b == b'\n').count();
+            last_byte = node.data.bytes().last().or(last_byte);
+        }
+
+        match last_byte {
+            None => 0,
+            Some(b'\n') => newlines,
+            Some(_) => newlines + 1,
+        }
+    }
+
+    // The byte range of line n (counting from 0), not including its newline.
+    // Returns None if there are not that many lines (see `line_count`).
+    pub fn line_range(&self, n: usize) -> Option<Range<usize>> {
+        let mut line = 0;
+        let mut start = 0;
+        let mut offset = 0;
+        for node in self.nodes() {
+            for (i, b) in node.data.bytes().enumerate() {
+                if b == b'\n' {
+                    if line == n {
+                        return Some(start..offset + i);
+                    }
+                    line += 1;
+                    start = offset + i + 1;
+                }
+            }
+            offset += node.data.len();
+        }
+
+        // The last line, if it does not end with a newline.
+        if line == n && start < self.len {
+            Some(start..self.len)
+        } else {
+            None
+        }
+    }
+
+    // Returns a copy of the text in range, or None if the range is out of
+    // bounds or reversed, or does not start and end on char boundaries.
+    pub fn get(&self, Range { start, end }: Range<usize>) -> Option<String> {
+        if start > end || end > self.len ||
+           !self.is_char_boundary(start) || !self.is_char_boundary(end) {
+            return None;
+        }
+
+        let mut result = String::with_capacity(end - start);
+        let mut offset = 0;
+        for node in self.nodes() {
+            let node_end = offset + node.data.len();
+            if node_end > start && offset < end {
+                let from = cmp::max(start, offset) - offset;
+                let to = cmp::min(end, node_end) - offset;
+                result.push_str(&node.data[from..to]);
+            }
+            offset = node_end;
+        }
+        Some(result)
+    }
+
+    // The number of chars in range, which must start and end on char
+    // boundaries.
+    pub fn char_count_in(&self, Range { start, end }: Range<usize>) -> usize {
+        assert!(start <= end && end <= self.len,
+                "range {}..{} out of bounds for buffer of length {}",
+                start,
+                end,
+                self.len);
+        assert!(self.is_char_boundary(start) && self.is_char_boundary(end),
+                "range {}..{} is not on char boundaries",
+                start,
+                end);
+
+        let mut count = 0;
+        let mut offset = 0;
+        for node in self.nodes() {
+            let node_end = offset + node.data.len();
+            if node_end > start && offset < end {
+                let from = cmp::max(start, offset) - offset;
+                let to = cmp::min(end, node_end) - offset;
+                count += node.data[from..to].chars().count();
+            }
+            offset = node_end;
+        }
+        count
+    }
+
+    // Whether offset is the start of a char, or the end of the buffer.
+    pub fn is_char_boundary(&self, offset: usize) -> bool {
+        if offset > self.len {
+            return false;
+        }
+
+        let mut start = 0;
+        for node in self.nodes() {
+            if offset < start + node.data.len() {
+                return node.data.is_char_boundary(offset - start);
+            }
+            start += node.data.len();
+        }
+        true
+    }
+
+    // Like `chars`, but starts iterating at byte offset `byte`, which must be on
+    // a char boundary. The yielded offsets are still from the start of the buffer.
+    pub fn char_indices_from(&self, byte: usize) -> Chars<'_> {
+        assert!(byte <= self.len, "offset {} out of bounds for buffer of length {}", byte, self.len);
+
+        let mut offset = 0;
+        for node in self.nodes() {
+            if byte <= offset + node.data.len() {
+                let cur_byte = byte - offset;
+                assert!(node.data.is_char_boundary(cur_byte), "offset {} is not on a char boundary", byte);
+                return Chars {
+                    cur_node: node,
+                    cur_byte,
+                    abs_byte: byte,
+                    len: self.len,
+                };
+            }
+            offset += node.data.len();
+        }
+        unreachable!("offset is in bounds, but not in any node");
+    }
+
+    // A cursor positioned at byte offset byte, which must be on a char
+    // boundary.
+    pub fn cursor_at(&self, byte: usize) -> Cursor<'_> {
+        assert!(byte <= self.len, "offset {} out of bounds for buffer of length {}", byte, self.len);
+
+        let mut nodes = vec![];
+        let mut offset = 0;
+        for node in self.nodes() {
+            nodes.push(node);
+            if byte <= offset + node.data.len() {
+                let cur_byte = byte - offset;
+                assert!(node.data.is_char_boundary(cur_byte), "offset {} is not on a char boundary", byte);
+                return Cursor {
+                    nodes,
+                    node_start: offset,
+                    cur_byte,
+                };
+            }
+            offset += node.data.len();
+        }
+        unreachable!("offset is in bounds, but not in any node");
+    }
+
+    // Like `chars`, but starts iterating at the start of the node with index
+    // node_index (see `chunks`). Returns None if there is no such node. The
+    // yielded offsets are still from the start of the buffer.
+    pub fn chars_at_node(&self, node_index: usize) -> Option<Chars<'_>> {
+        let mut offset = 0;
+        for (i, node) in self.nodes().enumerate() {
+            if i == node_index {
+                return Some(Chars {
+                    cur_node: node,
+                    cur_byte: 0,
+                    abs_byte: offset,
+                    len: self.len,
+                });
+            }
+            offset += node.data.len();
+        }
+        None
+    }
+
+    // Iterators over the chars before and after byte, which must be on a char
+    // boundary. The buffer is not copied or modified.
+    pub fn split_at(&self, byte: usize) -> (Chars<'_>, Chars<'_>) {
+        let after = self.char_indices_from(byte);
+        (Chars::new(&self.first, byte), after)
+    }
+
+    // Writes the contents of the buffer to w, without building a String.
+    pub fn write_to<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        for chunk in self.chunks() {
+            w.write_all(chunk.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    // The contents of the buffer as a string, which is borrowed without copying
+    // if the buffer has a single node.
+    pub fn as_cow(&self) -> Cow<'_, str> {
+        if self.first.next.is_none() {
+            Cow::Borrowed(&self.first.data)
+        } else {
+            Cow::Owned(self.to_string())
+        }
+    }
+
+    // Consumes the buffer, returning its bytes. If the buffer has a single node,
+    // its allocation is reused rather than copied.
+    pub fn into_bytes(self) -> Vec<u8> {
+        if self.first.next.is_none() {
+            return self.first.data.into_bytes();
+        }
+
+        let mut result = Vec::with_capacity(self.len);
+        for chunk in self.chunks() {
+            result.extend_from_slice(chunk.as_bytes());
+        }
+        result
+    }
+
+    // An iterator over the contiguous chunks of text which make up the buffer.
+    // Chunks never split a char, but may be empty.
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks {
+            nodes: self.nodes(),
+        }
+    }
+
     pub fn chars<'a>(&'a self) -> Chars<'a> {
-        Chars::new(&self.first)
+        Chars::new(&self.first, self.len)
+    }
+
+    // A non-panicking version of `chars`, for validating buffers which might
+    // contain invalid UTF-8.
+    pub fn try_chars(&self) -> TryChars<'_> {
+        TryChars {
+            chars: self.chars(),
+        }
+    }
+
+    // Checks the internal invariants of the buffer, returning a description of
+    // the first which does not hold. This walks every node, so is intended for
+    // tests and fuzzing.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut len = 0;
+        for (i, node) in self.nodes().enumerate() {
+            // Chars must not be split between nodes, which `push_bytes_unchecked`
+            // relies on its callers for.
+            if let Err(e) = str::from_utf8(node.data.as_bytes()) {
+                return Err(format!("node {} is not valid UTF-8: {}", i, e));
+            }
+            len += node.data.len();
+        }
+        if len != self.len {
+            return Err(format!("buffer length is {}, but its nodes hold {} bytes", self.len, len));
+        }
+        if !self.last_is_valid() {
+            return Err("`last` is not the last node of the buffer".to_owned());
+        }
+        Ok(())
+    }
+
+    // Whether `last` points to the last node in the list owned by self. This
+    // walks every node, so debug assertions on hot paths just check that
+    // `last` has no next node.
+    fn last_is_valid(&self) -> bool {
+        self.nodes().last().is_some_and(|n| ::std::ptr::eq(n, self.last))
+    }
+
+    // Graphemes which are split between nodes are joined together. Note that
+    // this copies the text of each node, and allocates a String per grapheme.
+    #[cfg(feature = "unicode")]
+    pub fn graphemes(&self) -> Graphemes<'_> {
+        Graphemes {
+            nodes: self.nodes(),
+            window: String::new(),
+            window_start: 0,
+            starts: vec![],
+            next: 0,
+        }
+    }
+
+    // Compares the buffers, ignoring ASCII case, like
+    // `str::eq_ignore_ascii_case`.
+    pub fn eq_ignore_ascii_case(&self, other: &StringBuffer) -> bool {
+        self.len == other.len &&
+        self.bytes().zip(other.bytes()).all(|(a, b)| a.eq_ignore_ascii_case(&b))
+    }
+
+    // The number of bytes at the start of self and other which are the same.
+    // Chars are compared, so the result is always on a char boundary.
+    pub fn common_prefix_len(&self, other: &StringBuffer) -> usize {
+        self.chars()
+            .zip(other.chars())
+            .take_while(|&((a, _), (b, _))| a == b)
+            .map(|((c, _), _)| c.len_utf8())
+            .sum()
+    }
+
+    // The mutable version of `nodes`. Callers must keep `len` and `last` valid.
+    fn for_each_node_mut<F: FnMut(&mut StringNode)>(&mut self, mut f: F) {
+        let mut node = Some(&mut *self.first);
+        while let Some(n) = node {
+            f(n);
+            node = n.next.as_deref_mut();
+        }
+    }
+
+    fn bytes(&self) -> impl Iterator<Item = u8> + '_ {
+        self.nodes().flat_map(|n| n.data.bytes())
+    }
+
+    // Walks the nodes without recursion, so is safe for very long lists.
+    fn nodes(&self) -> Nodes<'_> {
+        Nodes {
+            next: Some(&self.first),
+        }
     }
 }
 
@@ -136,9 +981,9 @@ impl StringNode {
     }
 
     // Returns a reference to the new last node. 
-    fn push_str(&mut self, text: &str) -> &mut StringNode {
+    fn push_str(&mut self, text: &str, growth: GrowthPolicy) -> &mut StringNode {
         if let Some(ref mut n) = self.next {
-            return n.push_str(text);
+            return n.push_str(text, growth);
         }
 
         if self.data.capacity() - self.data.len() >= text.len() {
@@ -146,17 +991,52 @@ impl StringNode {
             self
         } else {
             self.data.shrink_to_fit();
-            let next_cap = cmp::min(cmp::max(self.data.capacity(),
-                                             INIT_CAPACITY) * 2,
-                                    MAX_CAPACITY);
-            let next_cap = cmp::max(next_cap, text.len());
+            let next_cap = self.next_capacity(text.len(), growth);
             self.next = Some(Box::new(StringNode::with_capacity(next_cap)));
             let next = self.next.as_mut().unwrap();
-            next.push_str(text);
+            next.push_str(text, growth);
             &mut **next
         }
     }
 
+    // Like `push_str`, but self must be the last node. Any incomplete char at
+    // the end of this node is moved to a new node along with bytes, so that
+    // chars are not split between nodes.
+    unsafe fn push_bytes(&mut self, bytes: &[u8], growth: GrowthPolicy) -> &mut StringNode {
+        debug_assert!(self.next.is_none());
+        if self.data.capacity() - self.data.len() >= bytes.len() {
+            self.data.as_mut_vec().extend_from_slice(bytes);
+            return self;
+        }
+
+        let data = self.data.as_mut_vec();
+        let incomplete = match data.iter().rposition(|&b| b & 0xc0 != 0x80) {
+            Some(i) if i + utf8_char_width(data[i]) > data.len() => i,
+            _ => data.len(),
+        };
+        let tail = data.split_off(incomplete);
+        data.shrink_to_fit();
+
+        let next_cap = self.next_capacity(tail.len() + bytes.len(), growth);
+        let mut next = StringNode::with_capacity(next_cap);
+        next.data.as_mut_vec().extend_from_slice(&tail);
+        next.data.as_mut_vec().extend_from_slice(bytes);
+        self.next = Some(Box::new(next));
+        self.next.as_deref_mut().unwrap()
+    }
+
+    // The capacity for a node following this one, which must hold at least
+    // min_len bytes.
+    fn next_capacity(&self, min_len: usize, growth: GrowthPolicy) -> usize {
+        let next_cap = match growth {
+            GrowthPolicy::Geometric { factor, max } => {
+                cmp::min(cmp::max(self.data.capacity(), INIT_CAPACITY).saturating_mul(factor), max)
+            }
+            GrowthPolicy::Fixed(capacity) => capacity,
+        };
+        cmp::max(next_cap, min_len)
+    }
+
     // Returns the length of the string stored in the list starting in this node
     fn total_len(&self) -> usize {
         self.data.len() + self.next.as_ref().map(|next| next.total_len()).unwrap_or(0)
@@ -181,6 +1061,27 @@ impl StringNode {
     }
 }
 
+// Node capacity doubles, up to 64kB. This is how buffers grew before growth
+// policies were added, and is used by `new` and `with_capacity`.
+impl Default for GrowthPolicy {
+    fn default() -> GrowthPolicy {
+        GrowthPolicy::Geometric {
+            factor: 2,
+            max: MAX_CAPACITY,
+        }
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+}
+
+impl Default for StringBuffer {
+    fn default() -> StringBuffer {
+        StringBuffer::new()
+    }
+}
+
 impl FromStr for StringBuffer {
     type Err = ();
     fn from_str(text: &str) -> Result<StringBuffer, ()> {
@@ -192,33 +1093,34 @@ impl FromStr for StringBuffer {
 
 impl fmt::Display for StringBuffer {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        fn fmt_node(node: &StringNode, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-            try!(write!(fmt, "{}", node.data));
-            if let Some(ref n) = node.next {
-                fmt_node(n, fmt)
-            } else {
-                Ok(())
-            }
+        for node in self.nodes() {
+            fmt.write_str(&node.data)?;
         }
-
-        fmt_node(&self.first, fmt)
+        Ok(())
     }
 }
 
 impl fmt::Debug for StringBuffer {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        try!(write!(f, "StringBuffer[{:?}", self.first.data));
-
-        let mut current = &self.first;
-        while let Some(next) = current.next.as_ref() {
-            try!(write!(f, ", {:?}", next.data));
-            current = next;
+        f.write_str("StringBuffer[")?;
+        for (i, node) in self.nodes().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{:?}", node.data)?;
         }
-
-        write!(f, "]")
+        f.write_str("]")
     }
 }
 
+// The raw `last` pointer makes StringBuffer !Send and !Sync by default. It is
+// safe to implement them because `last` only ever points to a node owned by
+// self (nodes are boxed, so moving the buffer does not invalidate it), and it
+// is only written through `&mut self`.
+unsafe impl Send for StringBuffer {}
+unsafe impl Sync for StringBuffer {}
+
+#[allow(deprecated)]
 impl PartialEq for StringBuffer {
     fn eq(&self, other: &StringBuffer) -> bool {
         // Shortcut if sizes differ
@@ -232,10 +1134,39 @@ impl PartialEq for StringBuffer {
 
 impl Eq for StringBuffer {}
 
-impl<'a> Iterator for Chars<'a> {
-    type Item = (char, usize);
-
+// Serialises as the logical string, so the node layout is not part of the
+// serialised form.
+#[cfg(feature = "serde")]
+impl ::serde::Serialize for StringBuffer {
+    fn serialize<S: ::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> ::serde::Deserialize<'de> for StringBuffer {
+    fn deserialize<D: ::serde::Deserializer<'de>>(deserializer: D) -> Result<StringBuffer, D::Error> {
+        let text: String = ::serde::Deserialize::deserialize(deserializer)?;
+        Ok(text.parse().unwrap())
+    }
+}
+
+impl<'a> IntoIterator for &'a StringBuffer {
+    type Item = (char, usize);
+    type IntoIter = Chars<'a>;
+
+    fn into_iter(self) -> Chars<'a> {
+        self.chars()
+    }
+}
+
+impl<'a> Iterator for Chars<'a> {
+    type Item = (char, usize);
+
     fn next(&mut self) -> Option<(char, usize)> {
+        if self.abs_byte >= self.len {
+            return None;
+        }
         while self.cur_byte >= self.cur_node.data.len() {
             if let Some(ref n) = self.cur_node.next {
                 self.cur_byte = 0;
@@ -250,14 +1181,106 @@ impl<'a> Iterator for Chars<'a> {
 
         return Some((result, byte));
     }
+
+    // Each char is between one and four bytes long.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining_bytes();
+        (remaining.div_ceil(4), Some(remaining))
+    }
 }
 
+impl<'a> Iterator for TryChars<'a> {
+    type Item = Result<(char, usize), Utf8Error>;
+
+    fn next(&mut self) -> Option<Result<(char, usize), Utf8Error>> {
+        let chars = &mut self.chars;
+        if chars.abs_byte >= chars.len {
+            return None;
+        }
+        while chars.cur_byte >= chars.cur_node.data.len() {
+            chars.cur_node = chars.cur_node.next.as_deref()?;
+            chars.cur_byte = 0;
+        }
+
+        let byte = chars.abs_byte;
+        Some(chars.try_read_char().map(|c| (c, byte)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chars.size_hint()
+    }
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = &'a StringNode;
+
+    fn next(&mut self) -> Option<&'a StringNode> {
+        let node = self.next?;
+        self.next = node.next.as_deref();
+        Some(node)
+    }
+}
+
+impl Iterator for Drain {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.text[self.pos..].chars().next()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.nodes.next().map(|node| &node.data[..])
+    }
+}
+
+#[cfg(feature = "unicode")]
+impl<'a> Iterator for Graphemes<'a> {
+    type Item = (String, usize);
+
+    fn next(&mut self) -> Option<(String, usize)> {
+        loop {
+            if self.next + 1 < self.starts.len() {
+                let (start, end) = (self.starts[self.next], self.starts[self.next + 1]);
+                self.next += 1;
+                return Some((self.window[start..end].to_owned(), self.window_start + start));
+            }
+
+            // Only the last grapheme in the window is left, and it may continue
+            // into the next node.
+            let last = self.starts.get(self.next).cloned().unwrap_or(self.window.len());
+            match self.nodes.next() {
+                Some(node) => {
+                    self.window = format!("{}{}", &self.window[last..], node.data);
+                    self.window_start += last;
+                    self.starts = self.window.grapheme_indices(true).map(|(i, _)| i).collect();
+                    self.next = 0;
+                }
+                None => {
+                    if self.next < self.starts.len() {
+                        self.next += 1;
+                        return Some((self.window[last..].to_owned(), self.window_start + last));
+                    }
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+#[allow(deprecated)]
 impl Clone for StringBuffer {
     fn clone(&self) -> StringBuffer {
         let mut result = StringBuffer {
             first: Box::new(self.first.flat_clone()),
             last: 0 as *mut StringNode,
-            len: self.len
+            len: self.len,
+            growth: self.growth,
         };
 
         {
@@ -266,6 +1289,7 @@ impl Clone for StringBuffer {
 
             while let Some(next_orig) = last_orig.next.as_ref() {
                 last.next = Some(Box::new(next_orig.flat_clone()));
+                last = last.next.as_deref_mut().unwrap();
                 last_orig = next_orig;
             }
 
@@ -276,15 +1300,66 @@ impl Clone for StringBuffer {
     }
 }
 
+impl<'a> Cursor<'a> {
+    // The byte offset of the cursor in the buffer.
+    pub fn offset(&self) -> usize {
+        self.node_start + self.cur_byte
+    }
+
+    // Moves the cursor forward over the next char and returns it, or returns
+    // None if the cursor is at the end of the buffer.
+    pub fn next_char(&mut self) -> Option<char> {
+        let mut node = self.cur_node();
+        while self.cur_byte >= node.data.len() {
+            let next = node.next.as_deref()?;
+            self.node_start += node.data.len();
+            self.cur_byte = 0;
+            self.nodes.push(next);
+            node = next;
+        }
+
+        let c = node.data[self.cur_byte..].chars().next().unwrap();
+        self.cur_byte += c.len_utf8();
+        Some(c)
+    }
+
+    // Moves the cursor back over the previous char and returns it, or returns
+    // None if the cursor is at the start of the buffer.
+    pub fn prev_char(&mut self) -> Option<char> {
+        while self.cur_byte == 0 {
+            if self.nodes.len() == 1 {
+                return None;
+            }
+            self.nodes.pop();
+            self.cur_byte = self.cur_node().data.len();
+            self.node_start -= self.cur_byte;
+        }
+
+        let c = self.cur_node().data[..self.cur_byte].chars().next_back().unwrap();
+        self.cur_byte -= c.len_utf8();
+        Some(c)
+    }
+
+    fn cur_node(&self) -> &'a StringNode {
+        self.nodes[self.nodes.len() - 1]
+    }
+}
+
 impl<'a> Chars<'a> {
-    fn new<'b>(first_node: &'b StringNode) -> Chars<'b> {
+    fn new(first_node: &StringNode, len: usize) -> Chars<'_> {
         Chars {
             cur_node: first_node,
             cur_byte: 0,
             abs_byte: 0,
+            len,
         }
     }
 
+    // The number of bytes of the buffer which have not been iterated over.
+    pub fn remaining_bytes(&self) -> usize {
+        self.len - self.abs_byte
+    }
+
     fn read_char(&mut self) -> char {
         let first_byte = self.read_byte();
         let width = utf8_char_width(first_byte);
@@ -308,6 +1383,20 @@ impl<'a> Chars<'a> {
         }
     }
 
+    // Reads a char from the current node, or skips past the invalid bytes at
+    // the current position.
+    fn try_read_char(&mut self) -> Result<char, Utf8Error> {
+        let bytes = &self.cur_node.data.as_bytes()[self.cur_byte..];
+        let width = cmp::min(cmp::max(utf8_char_width(bytes[0]), 1), bytes.len());
+        let (result, read) = match str::from_utf8(&bytes[..width]) {
+            Ok(s) => (Ok(s.chars().next().unwrap()), width),
+            Err(e) => (Err(e), e.error_len().unwrap_or(width)),
+        };
+        self.cur_byte += read;
+        self.abs_byte += read;
+        result
+    }
+
     fn read_byte(&mut self) -> u8 {
         let result = self.cur_node.data.as_bytes()[self.cur_byte];
         self.cur_byte += 1;
@@ -324,6 +1413,7 @@ mod test {
     use super::{StringNode, INIT_CAPACITY};
 
     #[test]
+    #[allow(deprecated)]
     fn test_new() {
         let s = StringBuffer::new();
         assert!(s.len == 0);
@@ -339,6 +1429,18 @@ mod test {
     }
 
     #[test]
+    fn test_default() {
+        let mut s = StringBuffer::default();
+        assert!(s == StringBuffer::new());
+        assert!(s.is_empty());
+        assert!(count_nodes(&s) == 1);
+        assert!(first_capacity(&s) == INIT_CAPACITY);
+        s.push_str("Hello");
+        assert!(s.to_string() == "Hello");
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn test_from_str() {
         let s: StringBuffer = "Hello".parse().unwrap();
         assert!(s.len == 5);
@@ -354,6 +1456,7 @@ mod test {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_push_str() {
         let mut s: StringBuffer = "Hello".parse().unwrap();
         assert!(first_capacity(&s) == INIT_CAPACITY);
@@ -376,6 +1479,7 @@ mod test {
 
     // push_str requiring multiple nodes
     #[test]
+    #[allow(deprecated)]
     fn test_push_str_multi() {
         let mut s: StringBuffer = StringBuffer::with_capacity(2);
         assert!(first_capacity(&s) == 2);
@@ -397,6 +1501,569 @@ mod test {
     }
 
     #[test]
+    #[allow(deprecated)]
+    fn test_push_str_read_stress() {
+        let mut s = StringBuffer::with_capacity(4);
+        let mut expected = String::new();
+        for i in 0..2000 {
+            let text = if i % 3 == 0 {
+                format!("{}\u{a9}\n", i)
+            } else {
+                "x".repeat(i % 700)
+            };
+            s.push_str(&text);
+            expected.push_str(&text);
+
+            if i % 50 == 0 {
+                assert!(s.len == expected.len());
+                assert!(s.to_string() == expected);
+                let chars: Vec<(char, usize)> = s.chars().collect();
+                let expected_chars: Vec<(char, usize)> = expected.char_indices()
+                                                                 .map(|(i, c)| (c, i))
+                                                                 .collect();
+                assert!(chars == expected_chars);
+            }
+        }
+        assert!(count_nodes(&s) > 2);
+        assert!(s.to_string() == expected);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut s = StringBuffer::with_capacity(2);
+        s.push_str("He");
+        s.push_str("\u{a9}llo");
+
+        let mut result = vec![];
+        for (c, i) in &s {
+            result.push((c, i));
+        }
+        assert!(result == [('H', 0), ('e', 1), ('\u{a9}', 2), ('l', 4), ('l', 5), ('o', 6)]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_char_indices_from() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("Hell");
+        s.push_str("o \u{a9}world!");
+        s.push_str(&"\u{20ac}".repeat(200));
+        assert!(count_nodes(&s) == 3);
+
+        for n in 0..s.len + 1 {
+            if !s.to_string().is_char_boundary(n) {
+                continue;
+            }
+            let from: Vec<(char, usize)> = s.char_indices_from(n).collect();
+            let expected: Vec<(char, usize)> = s.chars().skip_while(|&(_, i)| i < n).collect();
+            assert!(from == expected);
+        }
+        assert!(s.char_indices_from(s.len).next().is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_char_indices_from_not_boundary() {
+        let s: StringBuffer = "a\u{a9}b".parse().unwrap();
+        s.char_indices_from(2);
+    }
+
+    #[test]
+    fn test_line_count() {
+        let s = StringBuffer::new();
+        assert!(s.line_count() == 0);
+
+        let s: StringBuffer = "Hello".parse().unwrap();
+        assert!(s.line_count() == 1);
+        let s: StringBuffer = "Hello\n".parse().unwrap();
+        assert!(s.line_count() == 1);
+        let s: StringBuffer = "\n".parse().unwrap();
+        assert!(s.line_count() == 1);
+        let s: StringBuffer = "\n\n".parse().unwrap();
+        assert!(s.line_count() == 2);
+
+        let mut s = StringBuffer::with_capacity(8);
+        s.push_str("Hello\nW");
+        s.push_str("orld!\n\nHow goes it?");
+        assert!(count_nodes(&s) == 2);
+        assert!(s.line_count() == 4);
+        s.push_str("\n");
+        assert!(s.line_count() == 4);
+        s.truncate(7);
+        assert!(s.line_count() == 2);
+        s.truncate(6);
+        assert!(s.line_count() == 1);
+        s.truncate(0);
+        assert!(s.line_count() == 0);
+    }
+
+    #[test]
+    fn test_line_range() {
+        let mut s = StringBuffer::with_capacity(8);
+        s.push_str("Hello\nW");
+        s.push_str("orld!\n\nHow goes it?");
+        assert!(count_nodes(&s) == 2);
+        let text = s.to_string();
+
+        assert!(s.line_range(0) == Some(0..5));
+        assert!(s.line_range(1) == Some(6..12));
+        assert!(&text[6..12] == "World!");
+        assert!(s.line_range(2) == Some(13..13));
+        assert!(s.line_range(3) == Some(14..26));
+        assert!(&text[14..26] == "How goes it?");
+        assert!(s.line_range(4).is_none());
+
+        s.push_str("\n");
+        assert!(s.line_range(3) == Some(14..26));
+        assert!(s.line_range(4).is_none());
+
+        let s = StringBuffer::new();
+        assert!(s.line_range(0).is_none());
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("ab");
+        push_node(&mut s, "\u{a9}");
+        push_node(&mut s, "cd");
+        assert!(!s.strip_prefix("b"));
+        assert!(!s.strip_prefix("ab\u{a9}d"));
+        assert!(!s.strip_prefix("ab\u{a9}cde"));
+        assert!(s.to_string() == "ab\u{a9}cd");
+
+        assert!(s.strip_prefix("ab\u{a9}c"));
+        assert!(s.to_string() == "d");
+        assert!(s.len() == 1);
+        assert!(count_nodes(&s) == 1);
+        assert!(s.last_is_valid());
+        assert!(s.strip_prefix(""));
+        assert!(s.strip_prefix("d"));
+        assert!(s.is_empty());
+        s.push_str("x");
+        assert!(s.to_string() == "x");
+    }
+
+    #[test]
+    fn test_strip_suffix() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("ab");
+        push_node(&mut s, "\u{a9}");
+        push_node(&mut s, "cd");
+        assert!(!s.strip_suffix("c"));
+        assert!(!s.strip_suffix("a\u{a9}cd"));
+        assert!(!s.strip_suffix("xab\u{a9}cd"));
+        assert!(s.to_string() == "ab\u{a9}cd");
+
+        assert!(s.strip_suffix("b\u{a9}cd"));
+        assert!(s.to_string() == "a");
+        assert!(s.len() == 1);
+        assert!(s.last_is_valid());
+        s.push_str("x");
+        assert!(s.to_string() == "ax");
+        assert!(s.strip_suffix("ax"));
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_retain() {
+        let mut s = StringBuffer::with_capacity(8);
+        s.push_str("Hello, W");
+        push_node(&mut s, "   ");
+        push_node(&mut s, "\u{a9}rld!\n How goes\tit?");
+        push_node(&mut s, " \n ");
+        assert!(count_nodes(&s) == 4);
+
+        s.retain(|c| !c.is_whitespace());
+        assert!(s.to_string() == "Hello,W\u{a9}rld!Howgoesit?");
+        assert!(s.len == 23);
+        assert!(count_nodes(&s) == 2);
+
+        // `last` must still be valid.
+        s.push_str(" ok");
+        assert!(s.to_string() == "Hello,W\u{a9}rld!Howgoesit? ok");
+
+        s.retain(|_| false);
+        assert!(s.to_string() == "");
+        assert!(s.len == 0);
+        assert!(count_nodes(&s) == 1);
+        s.push_str("ok");
+        assert!(s.to_string() == "ok");
+    }
+
+    #[test]
+    fn test_char_count_in() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("a\u{a9}b");
+        s.push_str("\u{1F600}\u{20ac}c");
+        push_node(&mut s, "d\u{e9}");
+        let text = s.to_string();
+        let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).chain(Some(text.len())).collect();
+        for &start in &boundaries {
+            for &end in boundaries.iter().filter(|&&end| end >= start) {
+                let expected = s.get(start..end).unwrap().chars().count();
+                assert!(s.char_count_in(start..end) == expected);
+            }
+        }
+        assert!(s.char_count_in(0..s.len()) == 8);
+        assert!(s.char_count_in(3..3) == 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_char_count_in_not_char_boundary() {
+        let s: StringBuffer = "a\u{a9}b".parse().unwrap();
+        s.char_count_in(0..2);
+    }
+
+    #[test]
+    fn test_get() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("Hell");
+        s.push_str("o \u{a9}world!");
+        assert!(count_nodes(&s) == 2);
+
+        assert!(s.get(0..4) == Some("Hell".to_owned()));
+        assert!(s.get(2..9) == Some("llo \u{a9}w".to_owned()));
+        assert!(s.get(6..14) == Some("\u{a9}world!".to_owned()));
+        assert!(s.get(14..14) == Some(String::new()));
+        // Out of range.
+        assert!(s.get(10..15).is_none());
+        assert!(s.get(15..15).is_none());
+        // Reversed.
+        let (start, end) = (5, 2);
+        assert!(s.get(start..end).is_none());
+        // Not on char boundaries.
+        assert!(s.get(7..10).is_none());
+        assert!(s.get(2..7).is_none());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_growth_policy() {
+        let mut s = StringBuffer::with_growth_policy(4, GrowthPolicy::Fixed(16));
+        for _ in 0..10 {
+            s.push_str("0123456789");
+        }
+        assert!(s.len == 100);
+        assert!(s.to_string() == "0123456789".repeat(10));
+        // One node of 4 bytes (unused), then nodes holding 10 bytes each.
+        assert!(count_nodes(&s) == 11);
+        s.push_str(&"x".repeat(20));
+        assert!(count_nodes(&s) == 12);
+
+        let growth = GrowthPolicy::Geometric { factor: 4, max: 0x1000 };
+        let mut s = StringBuffer::with_growth_policy(4, growth);
+        s.push_str("Hello");
+        s.push_str(&"x".repeat(1015));
+        assert!(count_nodes(&s) == 2);
+        s.push_str("!");
+        assert!(count_nodes(&s) == 3);
+        s.push_str(&"x".repeat(1020 * 4 - 1));
+        assert!(count_nodes(&s) == 3);
+        // Capped at max.
+        s.push_str("!");
+        s.push_str(&"x".repeat(0x1000 - 1));
+        assert!(count_nodes(&s) == 4);
+        s.push_str("!");
+        assert!(count_nodes(&s) == 5);
+
+        let s2 = s.clone();
+        assert!(s2.growth == growth);
+
+        // A huge factor is capped at max rather than overflowing.
+        let growth = GrowthPolicy::Geometric { factor: usize::MAX, max: 0x100 };
+        let mut s = StringBuffer::with_growth_policy(4, growth);
+        s.push_str("Hello");
+        s.push_str(&"x".repeat(0x100));
+        assert!(count_nodes(&s) == 3);
+    }
+
+    #[test]
+    fn test_push_bytes_unchecked() {
+        let mut s = StringBuffer::with_capacity(4);
+        let text = "ab\u{20ac}\u{a9}c";
+        let bytes = text.as_bytes();
+        unsafe {
+            // Splits the \u{20ac} between pushes, and the second push does not
+            // fit in the first node.
+            s.push_bytes_unchecked(&bytes[..3]);
+            s.push_bytes_unchecked(&bytes[3..6]);
+            s.push_bytes_unchecked(&bytes[6..]);
+        }
+        assert!(s.len() == bytes.len());
+        assert!(count_nodes(&s) == 2);
+        assert!(s.chunks().collect::<Vec<_>>() == vec!["ab", "\u{20ac}\u{a9}c"]);
+        assert!(s.to_string() == text);
+        assert!(s.try_chars().all(|r| r.is_ok()));
+        s.push_str("!");
+        assert!(s.to_string() == "ab\u{20ac}\u{a9}c!");
+    }
+
+    #[test]
+    fn test_insert_char() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("ac");
+        push_node(&mut s, "\u{a9}e");
+        s.insert_char(1, 'b');
+        assert!(s.to_string() == "abc\u{a9}e");
+        // At a node boundary, the char is added to the start of the second node.
+        s.insert_char(3, '\u{20ac}');
+        assert!(s.to_string() == "abc\u{20ac}\u{a9}e");
+        assert!(s.chunks().collect::<Vec<_>>() == vec!["abc", "\u{20ac}\u{a9}e"]);
+        s.insert_char(8, 'd');
+        s.insert_char(0, '\u{1F600}');
+        s.insert_char(s.len(), '!');
+        assert!(s.to_string() == "\u{1F600}abc\u{20ac}\u{a9}de!");
+        assert!(s.len() == 15);
+        assert!(s.last_is_valid());
+        s.push_str("?");
+        assert!(s.to_string() == "\u{1F600}abc\u{20ac}\u{a9}de!?");
+
+        let mut s = StringBuffer::new();
+        s.insert_char(0, '\u{a9}');
+        s.insert_str(0, "ab");
+        assert!(s.to_string() == "ab\u{a9}");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_insert_char_not_char_boundary() {
+        let mut s: StringBuffer = "a\u{a9}b".parse().unwrap();
+        s.insert_char(2, 'x');
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_append_from_reader() {
+        // Reads one byte at a time.
+        struct Trickle<'a>(&'a [u8]);
+        impl<'a> ::std::io::Read for Trickle<'a> {
+            fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+                if self.0.is_empty() || buf.is_empty() {
+                    return Ok(0);
+                }
+                buf[0] = self.0[0];
+                self.0 = &self.0[1..];
+                Ok(1)
+            }
+        }
+
+        let text = "H\u{e9}llo \u{a9} w\u{20ac}rld \u{1F600}!";
+        let mut s: StringBuffer = "> ".parse().unwrap();
+        let n = s.append_from_reader(&mut Trickle(text.as_bytes())).unwrap();
+        assert!(n == text.len());
+        assert!(s.to_string() == format!("> {}", text));
+        assert!(s.len == text.len() + 2);
+
+        // Larger than the internal buffer.
+        let text = "\u{a9}".repeat(0x1000);
+        let mut s = StringBuffer::new();
+        let n = s.append_from_reader(&mut text.as_bytes()).unwrap();
+        assert!(n == text.len());
+        assert!(s.to_string() == text);
+
+        let mut s = StringBuffer::new();
+        assert!(s.append_from_reader(&mut &b"ab\xffc"[..]).is_err());
+        assert!(s.to_string() == "ab");
+        let mut s = StringBuffer::new();
+        assert!(s.append_from_reader(&mut Trickle(b"ab\xc2")).is_err());
+        assert!(s.to_string() == "ab");
+    }
+
+    #[test]
+    fn test_try_chars() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("a\u{a9}");
+        push_node(&mut s, "b\u{20ac}");
+        let chars: Vec<(char, usize)> = s.try_chars().map(|r| r.unwrap()).collect();
+        assert!(chars == s.chars().collect::<Vec<_>>());
+
+        // Overwrite the second byte of the \u{a9}, and the last byte of the
+        // \u{20ac}, which is at the end of a node.
+        unsafe {
+            s.first.data.as_mut_vec()[2] = b'x';
+            s.first.next.as_mut().unwrap().data.as_mut_vec()[3] = 0xff;
+        }
+        let chars: Vec<_> = s.try_chars().collect();
+        assert!(chars.len() == 6);
+        assert!(chars[0] == Ok(('a', 0)));
+        assert!(chars[1].is_err());
+        assert!(chars[2] == Ok(('x', 2)));
+        assert!(chars[3] == Ok(('b', 3)));
+        assert!(chars[4].is_err());
+        assert!(chars[5].is_err());
+
+        assert!(StringBuffer::new().try_chars().next().is_none());
+    }
+
+    #[test]
+    fn test_chars_size_hint() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("ab\u{a9}");
+        s.push_str("\u{1F600}\u{20ac}c");
+        let mut chars = s.chars();
+        let mut count = s.to_string().chars().count();
+        assert!(chars.remaining_bytes() == 12);
+
+        loop {
+            let (lower, upper) = chars.size_hint();
+            assert!(lower <= count);
+            assert!(upper.unwrap() >= count);
+            if chars.next().is_none() {
+                break;
+            }
+            count -= 1;
+        }
+        assert!(count == 0);
+        assert!(chars.remaining_bytes() == 0);
+        assert!(chars.size_hint() == (0, Some(0)));
+
+        let chars = s.char_indices_from(4);
+        assert!(chars.remaining_bytes() == 8);
+        assert!(chars.size_hint() == (2, Some(8)));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_concat() {
+        let mut s1 = StringBuffer::with_capacity(4);
+        s1.push_str("Hell");
+        s1.push_str("o, ");
+        let s2: StringBuffer = "\u{a9}".parse().unwrap();
+        let mut s3 = StringBuffer::with_capacity(2);
+        s3.push_str("wo");
+        s3.push_str("rld!");
+        let nodes = count_nodes(&s1) + count_nodes(&s2) + count_nodes(&s3);
+
+        let mut s = StringBuffer::concat(vec![s1, s2, s3]);
+        assert!(s.to_string() == "Hello, \u{a9}world!");
+        assert!(s.len == 15);
+        assert!(count_nodes(&s) == nodes);
+
+        s.push_str("!");
+        assert!(s.to_string() == "Hello, \u{a9}world!!");
+        assert!(count_nodes(&s) == nodes);
+
+        let s = StringBuffer::concat(vec![]);
+        assert!(s.to_string() == "");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_len() {
+        let mut s = StringBuffer::with_capacity(4);
+        assert!(s.len() == s.len);
+        assert!(s.is_empty());
+        s.push_str("Hell");
+        s.push_str("o \u{a9}world!");
+        assert!(s.len() == s.len);
+        assert!(s.len() == 14);
+        assert!(!s.is_empty());
+        s.truncate(6);
+        assert!(s.len() == s.len);
+        assert!(s.len() == 6);
+        s.retain(|c| c != 'l');
+        assert!(s.len() == s.len);
+        assert!(s.len() == 4);
+        s.truncate(0);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let mut s = StringBuffer::with_capacity(8);
+        s.push_str("foo bar ");
+        s.push_str("foo\u{a9}");
+        s.replace_all("foo", "baz!");
+        assert!(s.to_string() == "baz! bar baz!\u{a9}");
+        assert!(s.len() == 15);
+        assert!(s.last_is_valid());
+
+        // A match split between nodes.
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("ab\u{a9}f");
+        push_node(&mut s, "o");
+        push_node(&mut s, "o\u{a9}fo");
+        s.replace_all("foo", "");
+        assert!(s.to_string() == "ab\u{a9}\u{a9}fo");
+        assert!(s.len() == 8);
+        s.replace_all("\u{a9}\u{a9}", "\u{ae}");
+        assert!(s.to_string() == "ab\u{ae}fo");
+
+        // Matches do not overlap.
+        let mut s = StringBuffer::with_capacity(2);
+        s.push_str("aa");
+        push_node(&mut s, "aaa");
+        s.replace_all("aa", "b");
+        assert!(s.to_string() == "bba");
+        assert!(s.len() == 3);
+
+        // No matches.
+        let mut s = StringBuffer::with_capacity(2);
+        s.push_str("ab");
+        push_node(&mut s, "c");
+        s.replace_all("bd", "x");
+        assert!(s.to_string() == "abc");
+        assert!(count_nodes(&s) == 2);
+
+        let mut s: StringBuffer = "ab".parse().unwrap();
+        s.replace_all("", "-");
+        assert!(s.to_string() == "-a-b-");
+        assert!(s.to_string() == "ab".replace("", "-"));
+    }
+
+    #[test]
+    fn test_trim_end_in_place() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("ab c");
+        push_node(&mut s, "d \t");
+        push_node(&mut s, "  ");
+        push_node(&mut s, "\n");
+        s.trim_end_in_place();
+        assert!(s.to_string() == "ab cd");
+        assert!(s.len() == 5);
+        assert!(s.last_is_valid());
+        s.push_str("!");
+        assert!(s.to_string() == "ab cd!");
+
+        let mut s = StringBuffer::new();
+        s.push_str(" \n ");
+        s.trim_end_in_place();
+        assert!(s.is_empty());
+        s.trim_end_in_place();
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn test_trim_start_in_place() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("  ");
+        push_node(&mut s, "\n");
+        push_node(&mut s, "\t a b");
+        push_node(&mut s, " c ");
+        s.trim_start_in_place();
+        assert!(s.to_string() == "a b c ");
+        assert!(s.len() == 6);
+        assert!(count_nodes(&s) == 2);
+        assert!(s.last_is_valid());
+        s.push_str("!");
+        assert!(s.to_string() == "a b c !");
+
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str(" ");
+        push_node(&mut s, "  ");
+        s.trim_start_in_place();
+        assert!(s.is_empty());
+        assert!(count_nodes(&s) == 1);
+        assert!(s.last_is_valid());
+        s.push_str("x");
+        assert!(s.to_string() == "x");
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn test_truncate() {
         // One node.
         let mut s: StringBuffer = "Hello world!".parse().unwrap();
@@ -435,6 +2102,7 @@ mod test {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_truncate_short() {
         let mut s: StringBuffer = StringBuffer::with_capacity(2);
         s.push_str("Ho");
@@ -446,6 +2114,7 @@ mod test {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_truncate_noop() {
         let mut s: StringBuffer = StringBuffer::with_capacity(2);
         s.push_str("Ho");
@@ -455,6 +2124,46 @@ mod test {
     }
 
     #[test]
+    fn test_reserve_exact() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("abc");
+        s.reserve_exact(1);
+        assert!(count_nodes(&s) == 1);
+
+        s.reserve_exact(100);
+        assert!(count_nodes(&s) == 2);
+        assert!(unsafe { (*s.last).data.capacity() } == 100);
+        assert!(s.capacity() == 103);
+        s.push_str(&"x".repeat(100));
+        assert!(count_nodes(&s) == 2);
+        assert!(s.len() == 103);
+
+        let mut s = StringBuffer::with_capacity(4);
+        s.reserve_exact(10);
+        assert!(count_nodes(&s) == 1);
+        assert!(first_capacity(&s) == 10);
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut s: StringBuffer = StringBuffer::with_capacity(10);
+        s.push_str("Hello\nWorld!\nHow goes it?");
+        assert!(count_nodes(&s) == 2);
+        s.truncate(12);
+
+        let capacity = s.capacity();
+        assert!(capacity > 12);
+        s.shrink_to_fit();
+        assert!(s.capacity() < capacity);
+        assert!(s.capacity() == 12);
+        assert!(s.to_string() == "Hello\nWorld!");
+
+        s.push_str("!");
+        assert!(s.to_string() == "Hello\nWorld!!");
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn test_cur_offset_no_newlines() {
         let mut s = StringBuffer::new();
         s.push_str("Hello, World!");
@@ -477,6 +2186,36 @@ mod test {
         assert_eq!(19, s.cur_offset());
     }
 
+    #[test]
+    fn test_cur_offset_crlf() {
+        let mut s = StringBuffer::with_capacity(10);
+        s.push_str("Hello\r\nW");
+        s.push_str("orld!\r\nHow goes it?");
+        assert!(s.cur_offset_crlf() == 12);
+        assert!(s.cur_offset() == 12);
+        assert!(s.line_count() == 3);
+
+        // The '\r' is pushed before its '\n'.
+        s.push_str("\r");
+        assert!(s.cur_offset_crlf() == 12);
+        assert!(s.cur_offset() == 13);
+        s.push_str("\n");
+        assert!(s.cur_offset_crlf() == 0);
+        assert!(s.line_count() == 3);
+
+        s.push_str("ab\rc");
+        assert!(s.cur_offset_crlf() == 4);
+
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("abc");
+        push_node(&mut s, "\r");
+        push_node(&mut s, "");
+        assert!(s.cur_offset_crlf() == 3);
+        assert!(StringBuffer::new().cur_offset_crlf() == 0);
+        let s: StringBuffer = "\r".parse().unwrap();
+        assert!(s.cur_offset_crlf() == 0);
+    }
+
     #[test]
     fn test_cur_offset_middle() {
         let mut s = StringBuffer::with_capacity(10);
@@ -498,6 +2237,398 @@ mod test {
         assert_eq!(3, s.cur_offset());
     }
 
+    #[test]
+    fn test_first_last_line_len() {
+        let mut s = StringBuffer::new();
+        assert!(s.first_line_len() == 0);
+        assert!(s.last_line_len() == 0);
+
+        s.push_str("Hello, World!");
+        assert!(s.first_line_len() == 13);
+        assert!(s.last_line_len() == 13);
+
+        let mut s = StringBuffer::with_capacity(10);
+        s.push_str("Hello");
+        s.push_str(" World!\nHow goes");
+        push_node(&mut s, " it?\n\u{a9}ok");
+        assert!(count_nodes(&s) == 3);
+        assert!(s.first_line_len() == 12);
+        assert!(s.last_line_len() == 4);
+
+        let mut s = StringBuffer::new();
+        s.push_str("\nfoo\n");
+        assert!(s.first_line_len() == 0);
+        assert!(s.last_line_len() == 0);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_send() {
+        let mut s = StringBuffer::with_capacity(2);
+        s.push_str("Hello");
+        let s = ::std::thread::spawn(move || {
+            s.push_str(" world!");
+            s
+        }).join().unwrap();
+        assert!(s.to_string() == "Hello world!");
+        assert!(s.len == 12);
+        assert!(count_nodes(&s) == 2);
+
+        let s = ::std::sync::Arc::new(s);
+        let s2 = s.clone();
+        let text = ::std::thread::spawn(move || s2.to_string()).join().unwrap();
+        assert!(text == "Hello world!");
+    }
+
+    #[test]
+    fn test_as_cow() {
+        let mut s = StringBuffer::with_capacity(8);
+        s.push_str("Hello");
+        assert!(matches!(s.as_cow(), Cow::Borrowed("Hello")));
+
+        s.push_str(" world!");
+        assert!(count_nodes(&s) == 2);
+        match s.as_cow() {
+            Cow::Owned(text) => assert!(text == "Hello world!"),
+            Cow::Borrowed(_) => panic!("expected an owned string"),
+        }
+
+        assert!(matches!(StringBuffer::new().as_cow(), Cow::Borrowed("")));
+    }
+
+    #[test]
+    fn test_cursor() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("a\u{a9}");
+        push_node(&mut s, "");
+        push_node(&mut s, "\u{20ac}b");
+        push_node(&mut s, "\u{1F600}");
+
+        let mut c = s.cursor_at(0);
+        assert!(c.prev_char().is_none());
+        let mut forwards = vec![];
+        while let Some(ch) = c.next_char() {
+            forwards.push((ch, c.offset()));
+        }
+        assert!(forwards == vec![('a', 1), ('\u{a9}', 3), ('\u{20ac}', 6), ('b', 7), ('\u{1F600}', 11)]);
+        assert!(c.offset() == s.len());
+
+        let mut backwards = vec![];
+        while let Some(ch) = c.prev_char() {
+            backwards.push((ch, c.offset()));
+        }
+        assert!(backwards == vec![('\u{1F600}', 7), ('b', 6), ('\u{20ac}', 3), ('\u{a9}', 1), ('a', 0)]);
+        assert!(c.offset() == 0);
+
+        // Back and forth across a node boundary.
+        let mut c = s.cursor_at(3);
+        assert!(c.next_char() == Some('\u{20ac}'));
+        assert!(c.prev_char() == Some('\u{20ac}'));
+        assert!(c.prev_char() == Some('\u{a9}'));
+        assert!(c.next_char() == Some('\u{a9}'));
+        assert!(c.offset() == 3);
+
+        let mut c = s.cursor_at(s.len());
+        assert!(c.next_char().is_none());
+        assert!(c.prev_char() == Some('\u{1F600}'));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_cursor_not_char_boundary() {
+        let s: StringBuffer = "a\u{a9}".parse().unwrap();
+        s.cursor_at(2);
+    }
+
+    #[test]
+    fn test_push_lines() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("abc");
+        s.push_lines(&["foo", "", "bar"], true);
+        assert!(s.to_string() == "abcfoo\n\nbar\n");
+        assert!(s.line_count() == 3);
+        assert!(count_nodes(&s) == 2);
+
+        s.push_lines(&["baz", "qux"], false);
+        assert!(s.to_string() == "abcfoo\n\nbar\nbaz\nqux");
+        assert!(s.line_count() == 5);
+
+        s.push_lines(&[], true);
+        assert!(s.len() == 19);
+    }
+
+    #[test]
+    fn test_common_prefix_len() {
+        let mut a = StringBuffer::with_capacity(4);
+        a.push_str("Hel");
+        push_node(&mut a, "lo \u{a9}");
+        push_node(&mut a, "world");
+        let b: StringBuffer = "Hello \u{a9}world".parse().unwrap();
+        assert!(a.common_prefix_len(&b) == a.len());
+        assert!(a.common_prefix_len(&a) == a.len());
+
+        let c: StringBuffer = "Goodbye".parse().unwrap();
+        assert!(a.common_prefix_len(&c) == 0);
+        assert!(a.common_prefix_len(&StringBuffer::new()) == 0);
+
+        let mut d = StringBuffer::with_capacity(4);
+        d.push_str("Hello \u{a9}");
+        push_node(&mut d, "wor");
+        push_node(&mut d, "d");
+        assert!(a.common_prefix_len(&d) == 11);
+        assert!(d.common_prefix_len(&a) == 11);
+
+        // Chars which differ only in their last byte.
+        let e: StringBuffer = "Hello \u{aa}".parse().unwrap();
+        assert!(a.common_prefix_len(&e) == 6);
+
+        let f: StringBuffer = "Hello".parse().unwrap();
+        assert!(a.common_prefix_len(&f) == 5);
+    }
+
+    #[test]
+    fn test_into_bytes() {
+        let mut s = StringBuffer::with_capacity(16);
+        s.push_str("Hello \u{a9}");
+        let ptr = s.first.data.as_ptr();
+        let expected = s.to_string().into_bytes();
+        let bytes = s.into_bytes();
+        assert!(bytes == expected);
+        assert!(bytes.as_ptr() == ptr);
+
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("Hel");
+        push_node(&mut s, "lo \u{a9}");
+        push_node(&mut s, "");
+        push_node(&mut s, "world");
+        let expected = s.to_string().into_bytes();
+        assert!(s.into_bytes() == expected);
+
+        assert!(StringBuffer::new().into_bytes().is_empty());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_validate() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("Hello \u{a9}");
+        push_node(&mut s, "world");
+        s.insert_str(2, "xyz");
+        s.truncate(12);
+        s.push_str("!");
+        assert!(s.validate().is_ok());
+        assert!(s.clone().validate().is_ok());
+        assert!(StringBuffer::new().validate().is_ok());
+
+        let mut bad = s.clone();
+        bad.len += 1;
+        assert!(bad.validate().is_err());
+
+        let mut bad = s.clone();
+        bad.last = &mut *bad.first;
+        assert!(bad.validate().is_err());
+
+        let mut bad = s.clone();
+        unsafe {
+            bad.push_bytes_unchecked(&[0xc2]);
+        }
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_clone_many_nodes() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("abc");
+        push_node(&mut s, "def");
+        push_node(&mut s, "ghi");
+        let mut c = s.clone();
+        assert!(count_nodes(&c) == 3);
+        assert!(c.last_is_valid());
+        c.push_str("jkl");
+        assert!(c.to_string() == "abcdefghijkl");
+        assert!(c.len() == 12);
+    }
+
+    #[test]
+    fn test_push_repeated() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("x");
+        s.push_repeated("ab", 1000);
+        assert!(s.len() == 2001);
+        assert!(count_nodes(&s) == 2);
+        assert!(s.to_string() == format!("x{}", "ab".repeat(1000)));
+
+        s.push_repeated("", 10);
+        s.push_repeated("cd", 0);
+        assert!(s.len() == 2001);
+        assert!(s.validate().is_ok());
+    }
+
+    #[test]
+    fn test_remove_range() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("Hel");
+        push_node(&mut s, "lo \u{a9}");
+        push_node(&mut s, "world");
+        s.remove_range(2..4);
+        assert!(s.to_string() == "Heo \u{a9}world");
+        assert!(s.validate().is_ok());
+
+        // Emptied nodes are unlinked, including the last.
+        s.remove_range(2..6);
+        assert!(s.to_string() == "Heworld");
+        assert!(count_nodes(&s) == 2);
+        s.remove_range(2..7);
+        assert!(s.to_string() == "He");
+        assert!(count_nodes(&s) == 1);
+        assert!(s.validate().is_ok());
+        s.push_str("y");
+        assert!(s.to_string() == "Hey");
+
+        s.remove_range(1..1);
+        s.remove_range(0..3);
+        assert!(s.is_empty());
+        assert!(s.validate().is_ok());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_remove_range_not_char_boundary() {
+        let mut s: StringBuffer = "a\u{a9}".parse().unwrap();
+        s.remove_range(0..2);
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("Hel");
+        push_node(&mut s, "lo \u{a9}");
+        push_node(&mut s, "world");
+        let drained: String = s.drain(2..9).collect();
+        assert!(drained == "llo \u{a9}w");
+        assert!(s.to_string() == "Heorld");
+        assert!(s.len() == 6);
+        assert!(s.validate().is_ok());
+
+        // The text is removed even if the iterator is not consumed.
+        let mut d = s.drain(1..5);
+        assert!(d.next() == Some('e'));
+        drop(d);
+        assert!(s.to_string() == "Hd");
+
+        assert!(s.drain(1..1).next().is_none());
+        assert!(s.drain(0..2).count() == 2);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_drain_not_char_boundary() {
+        let mut s: StringBuffer = "a\u{a9}".parse().unwrap();
+        s.drain(0..2);
+    }
+
+    #[test]
+    fn test_replace_range() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("Hel");
+        push_node(&mut s, "lo \u{a9}");
+        push_node(&mut s, "world");
+        s.replace_range(1..8, "i");
+        assert!(s.to_string() == "Hiworld");
+        s.replace_range(2..7, "!");
+        assert!(s.to_string() == "Hi!");
+        s.replace_range(3..3, "!!");
+        assert!(s.to_string() == "Hi!!!");
+        assert!(s.validate().is_ok());
+    }
+
+    #[test]
+    fn test_apply_edits() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("Hel");
+        push_node(&mut s, "lo \u{a9}");
+        push_node(&mut s, "world");
+        let mut expected = s.clone();
+
+        s.apply_edits(&[Edit::Insert { at: 5, text: ",".to_owned() },
+                        Edit::Replace { at: 8, text: "W".to_owned() },
+                        Edit::Remove { start: 6, end: 8 }]);
+
+        // Applying the edits by hand, from the end.
+        expected.replace_range(8..9, "W");
+        expected.remove_range(6..8);
+        expected.insert_str(5, ",");
+        assert!(s.to_string() == expected.to_string());
+        assert!(s.to_string() == "Hello, World");
+        assert!(s.validate().is_ok());
+    }
+
+    #[test]
+    fn test_chars_at_node() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("a\u{a9}b");
+        s.push_str("\u{20ac}c");
+        assert!(count_nodes(&s) == 2);
+
+        let chars: Vec<(char, usize)> = s.chars_at_node(1).unwrap().collect();
+        assert!(chars == vec![('\u{20ac}', 4), ('c', 7)]);
+        assert!(s.chars_at_node(1).unwrap().remaining_bytes() == 4);
+        assert!(s.chars_at_node(0).unwrap().eq(s.chars()));
+        assert!(s.chars_at_node(2).is_none());
+    }
+
+    #[test]
+    fn test_split_at() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("ab\u{a9}");
+        s.push_str("cd\u{20ac}e");
+        let text = s.to_string();
+        for (i, _) in text.char_indices().chain(Some((text.len(), ' '))) {
+            let (before, after) = s.split_at(i);
+            assert!(before.remaining_bytes() == i);
+            assert!(after.remaining_bytes() == text.len() - i);
+            let before: String = before.map(|(c, _)| c).collect();
+            let after: Vec<(char, usize)> = after.collect();
+            assert!(before == text[..i]);
+            assert!(after == text[i..].char_indices().map(|(j, c)| (c, i + j)).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_split_at_not_char_boundary() {
+        let s: StringBuffer = "a\u{a9}".parse().unwrap();
+        s.split_at(2);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("Hell");
+        s.push_str("o \u{a9}world!");
+        push_node(&mut s, "\n");
+        let chunks: Vec<&str> = s.chunks().collect();
+        assert!(chunks.len() == count_nodes(&s));
+        assert!(chunks.concat() == s.to_string());
+        assert!(chunks[0] == "Hell");
+
+        let s = StringBuffer::new();
+        assert!(s.chunks().collect::<Vec<_>>() == vec![""]);
+    }
+
+    #[test]
+    fn test_write_to() {
+        let mut s = StringBuffer::with_capacity(2);
+        s.push_str("Hello");
+        s.push_str(" \u{a9}world!");
+        assert!(count_nodes(&s) == 2);
+
+        let mut out = vec![];
+        s.write_to(&mut out).unwrap();
+        assert!(out == s.to_string().into_bytes());
+    }
+
     #[test]
     fn test_eq() {
         let s1: StringBuffer = "Hello".parse().unwrap();
@@ -505,6 +2636,41 @@ mod test {
         assert_eq!(s1, s2);
     }
 
+    #[test]
+    fn test_eq_ignore_ascii_case() {
+        let mut s1 = StringBuffer::with_capacity(4);
+        s1.push_str("fn M");
+        s1.push_str("ain() \u{e9}\u{c9}");
+        let s2: StringBuffer = "FN main() \u{e9}\u{c9}".parse().unwrap();
+        assert!(s1.eq_ignore_ascii_case(&s2));
+        assert!(s2.eq_ignore_ascii_case(&s1));
+
+        // Non-ASCII chars must match exactly.
+        let s3: StringBuffer = "FN main() \u{c9}\u{c9}".parse().unwrap();
+        assert!(!s1.eq_ignore_ascii_case(&s3));
+        let s4: StringBuffer = "FN main() \u{e9}".parse().unwrap();
+        assert!(!s1.eq_ignore_ascii_case(&s4));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_make_ascii_case() {
+        let mut s = StringBuffer::with_capacity(4);
+        s.push_str("fn M");
+        s.push_str("ain() \u{e9}\u{c9}");
+        assert!(count_nodes(&s) == 2);
+
+        s.make_ascii_uppercase();
+        assert!(s.to_string() == "FN MAIN() \u{e9}\u{c9}");
+        assert!(s.len == 14);
+        assert!(count_nodes(&s) == 2);
+
+        s.make_ascii_lowercase();
+        assert!(s.to_string() == "fn main() \u{e9}\u{c9}");
+        assert!(s.len == 14);
+        assert!(count_nodes(&s) == 2);
+    }
+
     #[test]
     #[should_panic]
     fn test_neq() {
@@ -529,6 +2695,81 @@ mod test {
         assert_eq!(s2.to_string(), "HelloWorld");
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let mut s: StringBuffer = StringBuffer::with_capacity(2);
+        s.push_str("Hello\n");
+        s.push_str("\"world\"");
+        assert!(count_nodes(&s) == 2);
+
+        let json = ::serde_json::to_string(&s).unwrap();
+        assert_eq!(json, "\"Hello\\n\\\"world\\\"\"");
+
+        let s2: StringBuffer = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(s, s2);
+        assert!(count_nodes(&s2) == 1);
+    }
+
+    #[test]
+    fn test_display_many_nodes() {
+        let mut s = StringBuffer::with_capacity(1);
+        let mut expected = String::new();
+        // The first node is empty.
+        let mut expected_debug = "StringBuffer[\"\"".to_owned();
+        for i in 0..10000 {
+            let text = format!("{},", i);
+            push_node(&mut s, &text);
+            expected.push_str(&text);
+            expected_debug.push_str(&format!(", \"{}\"", text));
+        }
+        expected_debug.push(']');
+        assert!(count_nodes(&s) == 10001);
+
+        assert!(s.to_string() == expected);
+        assert_eq!(format!("{:?}", s), expected_debug);
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut s = StringBuffer::with_capacity(2);
+        s.push_str("Hi");
+        s.push_str("\"there\"");
+        assert_eq!(format!("{:?}", s), "StringBuffer[\"Hi\", \"\\\"there\\\"\"]");
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn test_graphemes() {
+        use unicode_segmentation::UnicodeSegmentation;
+
+        let mut s = StringBuffer::with_capacity(0);
+        // A combining accent split from its base char.
+        push_node(&mut s, "cafe");
+        push_node(&mut s, "\u{301} ");
+        // An emoji ZWJ sequence split over three nodes.
+        push_node(&mut s, "\u{1F468}\u{200D}");
+        push_node(&mut s, "\u{1F469}");
+        push_node(&mut s, "\u{200D}\u{1F467}!");
+        // Flags, i.e., pairs of regional indicators.
+        push_node(&mut s, "\u{1F1EC}");
+        push_node(&mut s, "\u{1F1E7}\u{1F1EB}");
+        push_node(&mut s, "\u{1F1F7}");
+        push_node(&mut s, "");
+
+        let text = s.to_string();
+        let expected: Vec<(String, usize)> = text.grapheme_indices(true)
+                                                 .map(|(i, g)| (g.to_owned(), i))
+                                                 .collect();
+        let graphemes: Vec<(String, usize)> = s.graphemes().collect();
+        assert_eq!(graphemes, expected);
+        assert!(graphemes[3] == ("e\u{301}".to_owned(), 3));
+        assert!(graphemes[5].0 == "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}");
+        assert!(graphemes.len() == 9);
+
+        assert!(StringBuffer::new().graphemes().next().is_none());
+    }
+
     // TODO test unicode
 
     // Helper methods.
@@ -544,4 +2785,16 @@ mod test {
     fn first_capacity(s: &StringBuffer) -> usize {
         s.first.data.capacity()
     }
+    // Appends text to s in a new node.
+    #[allow(deprecated)]
+    fn push_node(s: &mut StringBuffer, text: &str) {
+        unsafe {
+            (*s.last).next = Some(Box::new(StringNode {
+                data: text.to_owned(),
+                next: None,
+            }));
+            s.last = &mut **(*s.last).next.as_mut().unwrap();
+        }
+        s.len += text.len();
+    }
 }
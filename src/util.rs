@@ -1,3 +1,5 @@
+use edit::Edit;
+
 // https://tools.ietf.org/html/rfc3629
 static UTF8_CHAR_WIDTH: [u8; 256] = [
 1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
@@ -23,3 +25,33 @@ static UTF8_CHAR_WIDTH: [u8; 256] = [
 pub fn utf8_char_width(b: u8) -> usize {
     return UTF8_CHAR_WIDTH[b as usize] as usize;
 }
+
+// Returns edits in the order in which they should be applied, so that applying
+// each edit does not move the text which later edits refer to. I.e., in
+// descending order of offset. At the same offset, removals and replacements go
+// before inserts, and inserts go in reverse, so that inserted text ends up in
+// the order of edits. Panics if any edits overlap or are out of bounds.
+pub fn edit_order(edits: &[Edit], len: usize) -> Vec<&Edit> {
+    let range = |edit: &Edit| match *edit {
+        Edit::Insert { at, .. } => (at, at),
+        Edit::Remove { start, end } => (start, end),
+        Edit::Replace { at, ref text } => (at, at + text.len()),
+    };
+
+    // Sort by offset, then inserts first, then position in edits.
+    let mut order: Vec<(usize, bool, usize)> = edits.iter().enumerate().map(|(i, e)| {
+        (range(e).0, !matches!(*e, Edit::Insert { .. }), i)
+    }).collect();
+    order.sort();
+
+    let mut prev_end = 0;
+    for &(_, _, i) in &order {
+        let (start, end) = range(&edits[i]);
+        assert!(start <= end, "edit {}..{} is reversed", start, end);
+        assert!(end <= len, "edit {}..{} out of bounds for text of length {}", start, end, len);
+        assert!(start >= prev_end, "edit {}..{} overlaps an earlier edit", start, end);
+        prev_end = end;
+    }
+
+    order.into_iter().rev().map(|(_, _, i)| &edits[i]).collect()
+}